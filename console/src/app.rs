@@ -175,6 +175,17 @@ impl FrontEnd {
                                     "Available: {:.2}",
                                     latest_account.balance.available
                                 ));
+                                if let Some(regime) = &latest_account.regime {
+                                    ui.label(" | ");
+                                    ui.add_space(3.0);
+                                    ui.label(RichText::new(format!("Market: {}", regime)).color(
+                                        match regime.as_str() {
+                                            "risk-on" => Color32::GREEN,
+                                            "risk-off" => Color32::RED,
+                                            _ => Color32::GRAY,
+                                        },
+                                    ));
+                                }
                             });
                             Plot::new("balance")
                                 .legend(legend.clone())
@@ -9,6 +9,8 @@ pub struct Account {
     pub fee_spend: f64,
     pub earnings: f64,
     pub change: f64,
+    #[serde(default)]
+    pub regime: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
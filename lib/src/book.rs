@@ -0,0 +1,289 @@
+use std::cmp::Ordering;
+
+use thiserror::Error;
+
+use crate::models::Book;
+
+//Okx computes the checksum over the first 25 levels of each side
+const CHECKSUM_DEPTH: usize = 25;
+pub const DEFAULT_BOOK_DEPTH: usize = 20;
+
+#[derive(Debug, Error)]
+pub enum BookError {
+    #[error("checksum mismatch for {inst_id}: expected {expected}, got {got}")]
+    Checksum {
+        inst_id: String,
+        expected: i64,
+        got: i64,
+    },
+    #[error("sequence gap for {inst_id}: expected prev_seq_id {expected}, got {got}")]
+    Sequence {
+        inst_id: String,
+        expected: i64,
+        got: i64,
+    },
+}
+
+impl BookError {
+    pub fn inst_id(&self) -> &str {
+        match self {
+            Self::Checksum { inst_id, .. } | Self::Sequence { inst_id, .. } => inst_id,
+        }
+    }
+}
+
+/// Price level as received from the exchange: [price, size, deprecated, num_orders].
+/// Kept as strings since the checksum is computed over the original representation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub entry: Vec<String>,
+}
+
+impl Level {
+    fn from_entry(entry: &[String]) -> Option<Self> {
+        let price = entry.first()?.parse::<f64>().ok()?;
+        Some(Self {
+            price,
+            entry: entry.to_vec(),
+        })
+    }
+
+    fn size(&self) -> &str {
+        self.entry.get(1).map(|s| s.as_str()).unwrap_or("0")
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size().parse::<f64>().unwrap_or(0.0) == 0.0
+    }
+}
+
+/// Local L2 book built from a snapshot and kept in sync with incremental updates
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    pub inst_id: String,
+    //Sorted best first: asks ascending, bids descending
+    pub asks: Vec<Level>,
+    pub bids: Vec<Level>,
+    pub seq_id: i64,
+    pub ts: String,
+}
+
+impl OrderBook {
+    pub fn from_snapshot(inst_id: &str, snapshot: &Book) -> Result<Self, BookError> {
+        let mut book = Self {
+            inst_id: inst_id.to_string(),
+            seq_id: snapshot.seq_id,
+            ts: snapshot.ts.clone(),
+            ..Default::default()
+        };
+        for entry in &snapshot.asks {
+            book.upsert(entry, true);
+        }
+        for entry in &snapshot.bids {
+            book.upsert(entry, false);
+        }
+        book.validate(snapshot.checksum)?;
+        Ok(book)
+    }
+
+    pub fn apply_update(&mut self, update: &Book) -> Result<(), BookError> {
+        //A heartbeat update has seq_id == prev_seq_id and no levels
+        if let Some(prev_seq_id) = update.prev_seq_id {
+            if prev_seq_id != self.seq_id {
+                return Err(BookError::Sequence {
+                    inst_id: self.inst_id.clone(),
+                    expected: self.seq_id,
+                    got: prev_seq_id,
+                });
+            }
+        }
+        for entry in &update.asks {
+            self.upsert(entry, true);
+        }
+        for entry in &update.bids {
+            self.upsert(entry, false);
+        }
+        self.seq_id = update.seq_id;
+        self.ts = update.ts.clone();
+        self.validate(update.checksum)
+    }
+
+    fn upsert(&mut self, entry: &[String], ask: bool) {
+        let level = match Level::from_entry(entry) {
+            Some(level) => level,
+            None => return,
+        };
+        let side = if ask { &mut self.asks } else { &mut self.bids };
+        let position = side.binary_search_by(|l| {
+            let ord = l.price.partial_cmp(&level.price).unwrap_or(Ordering::Equal);
+            if ask {
+                ord
+            } else {
+                ord.reverse()
+            }
+        });
+        match (position, level.is_empty()) {
+            (Ok(i), true) => {
+                side.remove(i);
+            },
+            (Ok(i), false) => side[i] = level,
+            (Err(_), true) => (),
+            (Err(i), false) => side.insert(i, level),
+        }
+    }
+
+    fn validate(&self, checksum: Option<i64>) -> Result<(), BookError> {
+        match checksum {
+            Some(expected) if expected != self.checksum() => Err(BookError::Checksum {
+                inst_id: self.inst_id.clone(),
+                expected,
+                got: self.checksum(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// CRC32 over "bid_px:bid_sz:ask_px:ask_sz:..." interleaving the top 25 levels,
+    /// interpreted as a signed 32 bit integer
+    pub fn checksum(&self) -> i64 {
+        let mut fields: Vec<&str> = Vec::with_capacity(CHECKSUM_DEPTH * 4);
+        for i in 0..CHECKSUM_DEPTH {
+            if let Some(bid) = self.bids.get(i) {
+                fields.push(&bid.entry[0]);
+                fields.push(bid.size());
+            }
+            if let Some(ask) = self.asks.get(i) {
+                fields.push(&ask.entry[0]);
+                fields.push(ask.size());
+            }
+        }
+        crc32fast::hash(fields.join(":").as_bytes()) as i32 as i64
+    }
+
+    /// Consistent snapshot of the best (depth) levels on each side
+    pub fn top(&self, depth: usize) -> Book {
+        let entries = |side: &[Level]| -> Vec<Vec<String>> {
+            side.iter().take(depth).map(|l| l.entry.clone()).collect()
+        };
+        Book {
+            asks: entries(&self.asks),
+            bids: entries(&self.bids),
+            checksum: Some(self.checksum()),
+            prev_seq_id: None,
+            seq_id: self.seq_id,
+            ts: self.ts.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(levels: &[(&str, &str)]) -> Vec<Vec<String>> {
+        levels
+            .iter()
+            .map(|(px, sz)| {
+                vec![
+                    px.to_string(),
+                    sz.to_string(),
+                    "0".to_string(),
+                    "1".to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    fn message(
+        asks: &[(&str, &str)],
+        bids: &[(&str, &str)],
+        prev_seq_id: Option<i64>,
+        seq_id: i64,
+        checksum: Option<i64>,
+    ) -> Book {
+        Book {
+            asks: entries(asks),
+            bids: entries(bids),
+            checksum,
+            prev_seq_id,
+            seq_id,
+            ts: "1597026383085".to_string(),
+        }
+    }
+
+    fn prices(side: &[Level]) -> Vec<&str> {
+        side.iter().map(|l| l.entry[0].as_str()).collect()
+    }
+
+    //Checksum example from the okx api docs: "3366.1:7:3366.8:9:3366:6:3368:8"
+    fn snapshot() -> OrderBook {
+        let snapshot = message(
+            &[("3366.8", "9"), ("3368", "8")],
+            &[("3366.1", "7"), ("3366", "6")],
+            None,
+            100,
+            Some(-1881014294),
+        );
+        OrderBook::from_snapshot("ETH-USDT", &snapshot).unwrap()
+    }
+
+    #[test]
+    fn snapshot_checksum() {
+        let book = snapshot();
+        assert_eq!(book.checksum(), -1881014294);
+        assert_eq!(prices(&book.asks), ["3366.8", "3368"]);
+        assert_eq!(prices(&book.bids), ["3366.1", "3366"]);
+    }
+
+    #[test]
+    fn delete_level() {
+        let mut book = snapshot();
+        let update = message(&[("3366.8", "0")], &[], Some(100), 101, Some(-388347743));
+        book.apply_update(&update).unwrap();
+        assert_eq!(prices(&book.asks), ["3368"]);
+        assert_eq!(prices(&book.bids), ["3366.1", "3366"]);
+        assert_eq!(book.seq_id, 101);
+    }
+
+    #[test]
+    fn insert_ordering() {
+        let mut book = snapshot();
+        let update = message(
+            &[("3370", "5"), ("3367", "4")],
+            &[("3365", "1"), ("3366.5", "2")],
+            Some(100),
+            101,
+            Some(-1655739060),
+        );
+        book.apply_update(&update).unwrap();
+        assert_eq!(prices(&book.asks), ["3366.8", "3367", "3368", "3370"]);
+        assert_eq!(prices(&book.bids), ["3366.5", "3366.1", "3366", "3365"]);
+    }
+
+    #[test]
+    fn sequence_gap() {
+        let mut book = snapshot();
+        let update = message(&[("3367", "4")], &[], Some(99), 101, None);
+        assert!(matches!(
+            book.apply_update(&update),
+            Err(BookError::Sequence {
+                expected: 100,
+                got: 99,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut book = snapshot();
+        let update = message(&[("3367", "4")], &[], Some(100), 101, Some(-1881014294));
+        assert!(matches!(
+            book.apply_update(&update),
+            Err(BookError::Checksum { .. })
+        ));
+        let snapshot = message(&[("3366.8", "9")], &[("3366.1", "7")], None, 100, Some(1));
+        assert!(OrderBook::from_snapshot("ETH-USDT", &snapshot).is_err());
+    }
+}
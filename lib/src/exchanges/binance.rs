@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Adapter, Normalized, SubscriptionEvent};
+use crate::models::*;
+
+//Binance doesn't enforce a hard limit, but big subscribe frames get dropped
+const MAX_STREAMS_PER_MSG: usize = 200;
+//Symbols have no separator, the first matching quote suffix is used
+const QUOTES: &[&str] = &[
+    "USDT", "USDC", "FDUSD", "TUSD", "BUSD", "BTC", "ETH", "BNB", "EUR", "TRY",
+];
+
+pub struct Binance {
+    book_depth: usize,
+}
+
+#[derive(Serialize, Debug, Deserialize)]
+pub struct BinanceSubscribeMsg {
+    pub method: String,
+    pub params: Vec<String>,
+    pub id: u64,
+}
+
+impl Binance {
+    pub fn new(book_depth: usize) -> Self {
+        Self { book_depth }
+    }
+
+    /// Stream name suffix for each channel (combined streams: `<symbol>@<stream>`)
+    pub fn stream(channel: Channel) -> &'static str {
+        match channel {
+            Channel::Candle1m => "kline_1m",
+            Channel::Tickers => "ticker",
+            Channel::Trades => "trade",
+            Channel::Books => "depth20@100ms",
+        }
+    }
+
+    /// BTC-USDT -> BTCUSDT
+    pub fn to_symbol(inst_id: &str) -> String {
+        inst_id.replace('-', "").to_uppercase()
+    }
+
+    /// BTCUSDT -> BTC-USDT
+    pub fn to_inst_id(symbol: &str) -> Option<String> {
+        let symbol = symbol.to_uppercase();
+        QUOTES.iter().find_map(|quote| {
+            symbol
+                .strip_suffix(quote)
+                .filter(|base| !base.is_empty())
+                .map(|base| format!("{}-{}", base, quote))
+        })
+    }
+
+    fn channel_from_stream(stream: &str) -> Option<Channel> {
+        match stream {
+            "kline_1m" => Some(Channel::Candle1m),
+            "ticker" => Some(Channel::Tickers),
+            "trade" => Some(Channel::Trades),
+            s if s.starts_with("depth") => Some(Channel::Books),
+            _ => None,
+        }
+    }
+
+    fn build_method(method: &str, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        let streams: Vec<String> = inst_ids
+            .iter()
+            .map(|i| {
+                format!(
+                    "{}@{}",
+                    Binance::to_symbol(i).to_lowercase(),
+                    Binance::stream(channel)
+                )
+            })
+            .collect();
+
+        streams
+            .chunks(MAX_STREAMS_PER_MSG)
+            .enumerate()
+            .map(|(id, params)| {
+                Ok(serde_json::to_string(&BinanceSubscribeMsg {
+                    method: method.to_string(),
+                    params: params.to_vec(),
+                    id: id as u64 + 1,
+                })?)
+            })
+            .collect()
+    }
+
+    pub fn parse_candle(data: &Value) -> Candlestick {
+        let k = &data["k"];
+        Candlestick {
+            open: str_to_f64(&k["o"]),
+            high: str_to_f64(&k["h"]),
+            low: str_to_f64(&k["l"]),
+            close: str_to_f64(&k["c"]),
+            //vol in quote currency
+            volume: str_to_f64(&k["q"]),
+            ts: k["t"].as_i64().unwrap_or(0),
+            range: 0.0,
+            change: 0.0,
+        }
+    }
+
+    pub fn parse_ticker(data: &Value) -> Ticker {
+        Ticker {
+            ask_px: str_or_default(&data["a"]),
+            ask_sz: str_or_default(&data["A"]),
+            bid_px: str_or_default(&data["b"]),
+            bid_sz: str_or_default(&data["B"]),
+            high24h: str_or_default(&data["h"]),
+            last: str_or_default(&data["c"]),
+            last_sz: str_or_default(&data["Q"]),
+            low24h: str_or_default(&data["l"]),
+            open24h: str_or_default(&data["o"]),
+            //No start of day prices on binance, use the rolling 24h open instead
+            sod_utc0: str_or_default(&data["o"]),
+            sod_utc8: str_or_default(&data["o"]),
+            ts: data["E"].as_i64().unwrap_or(0).to_string(),
+            vol24h: str_or_default(&data["v"]),
+            vol_ccy24h: str_or_default(&data["q"]),
+        }
+    }
+
+    pub fn parse_trade(data: &Value) -> Trade {
+        Trade {
+            px: str_or_default(&data["p"]),
+            //buyer is maker -> the aggressor was selling
+            side: if data["m"].as_bool().unwrap_or(false) {
+                "sell".to_string()
+            } else {
+                "buy".to_string()
+            },
+            sz: str_or_default(&data["q"]),
+            trade_id: data["t"].as_i64().unwrap_or(0).to_string(),
+            ts: data["T"].as_i64().unwrap_or(0).to_string(),
+        }
+    }
+
+    /// Partial depth streams push full snapshots, no local book needed
+    pub fn parse_book(data: &Value, depth: usize) -> Book {
+        //Use the okx layout for book entries: [price, size, deprecated, num_orders]
+        let levels = |side: &Value| -> Vec<Vec<String>> {
+            side.as_array()
+                .map(|levels| {
+                    levels
+                        .iter()
+                        .take(depth)
+                        .map(|l| {
+                            vec![
+                                str_or_default(&l[0]),
+                                str_or_default(&l[1]),
+                                "0".to_string(),
+                                "0".to_string(),
+                            ]
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Book {
+            asks: levels(&data["asks"]),
+            bids: levels(&data["bids"]),
+            checksum: None,
+            prev_seq_id: None,
+            seq_id: data["lastUpdateId"].as_i64().unwrap_or(0),
+            //Partial book depth streams don't include an event time
+            ts: (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000).to_string(),
+        }
+    }
+}
+
+impl Adapter for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn parse_symbols(&self, pairs: Vec<String>) -> Vec<String> {
+        pairs
+            .iter()
+            .filter_map(|s| Binance::to_inst_id(s))
+            .collect()
+    }
+
+    fn build_subscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        Binance::build_method("SUBSCRIBE", channel, inst_ids)
+    }
+
+    fn build_unsubscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        Binance::build_method("UNSUBSCRIBE", channel, inst_ids)
+    }
+
+    fn normalize(&self, msg: &Value) -> Result<Option<Normalized>> {
+        //Combined stream payload: {"stream": "btcusdt@kline_1m", "data": {...}}
+        let stream = match msg["stream"].as_str() {
+            Some(stream) => stream,
+            None => return Ok(None),
+        };
+        let (symbol, stream) = stream
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Invalid stream name: {}", stream))?;
+        let channel = match Binance::channel_from_stream(stream) {
+            Some(channel) => channel,
+            None => return Ok(None),
+        };
+        let inst_id = match Binance::to_inst_id(symbol) {
+            Some(inst_id) => inst_id,
+            None => return Ok(None),
+        };
+
+        let data = &msg["data"];
+        let data = match channel {
+            Channel::Candle1m => {
+                serde_json::to_string(&Binance::parse_candle(data).get_change().get_range())?
+            },
+            Channel::Tickers => serde_json::to_string(&Binance::parse_ticker(data))?,
+            Channel::Trades => serde_json::to_string(&Binance::parse_trade(data))?,
+            Channel::Books => serde_json::to_string(&Binance::parse_book(data, self.book_depth))?,
+        };
+        Ok(Some(Normalized {
+            channel,
+            inst_id,
+            data,
+        }))
+    }
+
+    fn volumes_url(&self) -> Option<String> {
+        Some("https://api.binance.com/api/v3/ticker/24hr".to_string())
+    }
+
+    fn parse_volumes(&self, res: &Value) -> Result<HashMap<String, f64>> {
+        let tickers = res
+            .as_array()
+            .ok_or_else(|| anyhow!("ticker/24hr failed: {}", res["msg"]))?;
+        Ok(tickers
+            .iter()
+            .filter_map(|t| {
+                let inst_id = Binance::to_inst_id(t["symbol"].as_str()?)?;
+                Some((inst_id, str_to_f64(&t["quoteVolume"])))
+            })
+            .collect())
+    }
+
+    //Requests are acked by id only ({"result": null, "id": 1}), so only rejections are reported
+    fn parse_subscription(&self, msg: &Value) -> Option<SubscriptionEvent> {
+        msg.get("error").map(|e| {
+            SubscriptionEvent::Failed(format!("{} {}", e["code"], str_or_default(&e["msg"])))
+        })
+    }
+}
+
+fn str_or_default(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn str_to_f64(value: &Value) -> f64 {
+    value.as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0)
+}
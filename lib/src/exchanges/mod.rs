@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::{
+    book::DEFAULT_BOOK_DEPTH,
+    models::{Candlestick, Channel},
+    Exchange,
+};
+
+pub mod binance;
+pub mod okx;
+
+/// Websocket message translated into the models stored by the consumer.
+#[derive(Debug, Clone)]
+pub struct Normalized {
+    pub channel: Channel,
+    pub inst_id: String,
+    pub data: String,
+}
+
+/// Subscription ack parsed from a websocket message
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionEvent {
+    //Instrument id
+    Subscribed(String),
+    //Instrument id the exchange doesn't know, the rest of the request is still subscribed
+    Invalid(String),
+    //Rejected request, with the exchange error
+    Failed(String),
+}
+
+/// Market data side of an exchange: symbol discovery, subscriptions and message parsing.
+/// Instrument ids are always in `BASE-QUOTE` format (ex: BTC-USDT) outside of the adapter.
+pub trait Adapter: Send + Sync {
+    /// Exchange name, as used by `crypto_markets` and the `Exchange` record header
+    fn name(&self) -> &'static str;
+    /// Map the exchange spot symbols to instrument ids. Quotes are filtered by the producer universe
+    fn parse_symbols(&self, symbols: Vec<String>) -> Vec<String>;
+    /// Build the subscribe messages for the selected channel
+    fn build_subscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>>;
+    /// Build the unsubscribe messages for the selected channel
+    fn build_unsubscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>>;
+    /// Parse a websocket message. Returns `None` for events, acks and unknown channels.
+    /// Order book messages fail with a `book::BookError` when the local book is out of sync
+    fn normalize(&self, msg: &Value) -> Result<Option<Normalized>>;
+    /// Parse subscribe acks and errors. Subscriptions aren't verified when `None` is always returned
+    fn parse_subscription(&self, _msg: &Value) -> Option<SubscriptionEvent> {
+        None
+    }
+    /// REST url returning the 1m candles of the instrument from `from` to `to` (ms, inclusive),
+    /// used to backfill gaps. `None` when the exchange isn't supported
+    fn history_candles_url(&self, _inst_id: &str, _from: i64, _to: i64) -> Option<String> {
+        None
+    }
+    /// Parse a history candles response, oldest first
+    fn parse_history_candles(&self, _res: &Value) -> Result<Vec<Candlestick>> {
+        Ok(Vec::new())
+    }
+    /// REST url returning the 24h volume of every spot instrument. `None` when the exchange
+    /// isn't supported
+    fn volumes_url(&self) -> Option<String> {
+        None
+    }
+    /// Parse a volumes response into 24h volumes (quote currency) by instrument id
+    fn parse_volumes(&self, _res: &Value) -> Result<HashMap<String, f64>> {
+        Ok(HashMap::new())
+    }
+}
+
+pub fn from_config(exchange: &Exchange) -> Result<Box<dyn Adapter>> {
+    let book_depth = exchange.book_depth.unwrap_or(DEFAULT_BOOK_DEPTH);
+    match exchange.name.to_lowercase().as_str() {
+        "okx" => Ok(Box::new(okx::Okx::new(book_depth))),
+        "binance" => Ok(Box::new(binance::Binance::new(book_depth))),
+        _ => Err(anyhow!("Unsupported exchange: {}", exchange.name)),
+    }
+}
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{Adapter, Normalized, SubscriptionEvent};
+use crate::{book::OrderBook, models::*};
+
+//okx rejects requests above 64kb, keep subscribe batches well below it
+const MAX_ARGS_PER_MSG: usize = 100;
+
+pub struct Okx {
+    book_depth: usize,
+    //Local L2 books by instrument, rebuilt from the snapshot sent on (re)subscribe
+    books: Mutex<HashMap<String, OrderBook>>,
+}
+
+impl Okx {
+    pub fn new(book_depth: usize) -> Self {
+        Self {
+            book_depth,
+            books: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn build_op(op: &str, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        inst_ids
+            .chunks(MAX_ARGS_PER_MSG)
+            .map(|inst_ids| {
+                let msg = SubscribeMsg {
+                    op: op.to_string(),
+                    args: Okx::build_args(channel, inst_ids),
+                };
+                Ok(serde_json::to_string(&msg)?)
+            })
+            .collect()
+    }
+
+    /// Merge a snapshot/update into the local book and return the top levels.
+    /// Updates received before the snapshot (ex: while resubscribing) are dropped
+    pub fn merge_book(&self, inst_id: &str, msg: &Value) -> Result<Option<Book>> {
+        let data = serde_json::from_value::<Book>(msg["data"][0].clone())?;
+        let mut books = self.books.lock().unwrap();
+        if msg["action"].as_str() == Some("snapshot") {
+            books.remove(inst_id);
+            let book = OrderBook::from_snapshot(inst_id, &data)?;
+            let top = book.top(self.book_depth);
+            books.insert(inst_id.to_string(), book);
+            return Ok(Some(top));
+        }
+        let book = match books.get_mut(inst_id) {
+            Some(book) => book,
+            None => return Ok(None),
+        };
+        if let Err(e) = book.apply_update(&data) {
+            books.remove(inst_id);
+            return Err(e.into());
+        }
+        Ok(Some(book.top(self.book_depth)))
+    }
+
+    pub fn build_args(channel: Channel, pairs: &[String]) -> Vec<SubscribeArg> {
+        let mut args: Vec<SubscribeArg> = Vec::new();
+        for i in pairs.iter() {
+            let arg = match channel {
+                Channel::Tickers | Channel::Candle1m => SubscribeArg {
+                    channel: channel.to_string(),
+                    inst_type: Some("SPOT".to_string()),
+                    inst_id: Some(i.to_string()),
+                },
+                Channel::Trades | Channel::Books => SubscribeArg {
+                    channel: channel.to_string(),
+                    inst_type: None,
+                    inst_id: Some(i.to_string()),
+                },
+            };
+            args.push(arg);
+        }
+        args
+    }
+
+    pub fn parse_candle(msg: &Value) -> Candlestick {
+        if msg["data"] != json!(null) {
+            Okx::candle_from_row(&msg["data"][0])
+        } else {
+            Candlestick::new()
+        }
+    }
+
+    /// Candle from a `[ts, o, h, l, c, vol, volCcy, volCcyQuote, confirm]` row
+    fn candle_from_row(x: &Value) -> Candlestick {
+        Candlestick {
+            open: x[1].as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0),
+            high: x[2].as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0),
+            low: x[3].as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0),
+            close: x[4].as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0),
+            //vol in USD
+            volume: x[6].as_str().unwrap_or_default().parse::<f64>().unwrap_or(0.0),
+            ts: x[0].as_str().unwrap_or_default().parse::<i64>().unwrap_or(0),
+            range: 0.0,
+            change: 0.0,
+        }
+    }
+}
+
+impl Adapter for Okx {
+    fn name(&self) -> &'static str {
+        "okx"
+    }
+
+    fn parse_symbols(&self, mut pairs: Vec<String>) -> Vec<String> {
+        //Spot symbols are already instrument ids
+        pairs.retain(|x| {
+            x.split_once('-')
+                .map_or(false, |(base, quote)| !base.is_empty() && !quote.is_empty())
+        });
+        pairs
+    }
+
+    fn build_subscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        Okx::build_op("subscribe", channel, inst_ids)
+    }
+
+    fn build_unsubscribe(&self, channel: Channel, inst_ids: &[String]) -> Result<Vec<String>> {
+        Okx::build_op("unsubscribe", channel, inst_ids)
+    }
+
+    fn normalize(&self, msg: &Value) -> Result<Option<Normalized>> {
+        let channel = match Channel::from_str(msg["arg"]["channel"].as_str().unwrap_or_default()) {
+            Ok(channel) => channel,
+            Err(_) => return Ok(None),
+        };
+        if msg["data"] == json!(null) {
+            return Ok(None);
+        }
+        let inst_id = msg["arg"]["instId"].as_str().unwrap_or_default().to_string();
+        let data = match channel {
+            Channel::Tickers => serde_json::to_string(&serde_json::from_value::<Ticker>(
+                msg["data"][0].clone(),
+            )?)?,
+            Channel::Trades => serde_json::to_string(&serde_json::from_value::<Trade>(
+                msg["data"][0].clone(),
+            )?)?,
+            Channel::Books => match self.merge_book(&inst_id, msg)? {
+                Some(book) => serde_json::to_string(&book)?,
+                None => return Ok(None),
+            },
+            Channel::Candle1m => {
+                serde_json::to_string(&Okx::parse_candle(msg).get_change().get_range())?
+            },
+        };
+        Ok(Some(Normalized {
+            channel,
+            inst_id,
+            data,
+        }))
+    }
+
+    fn parse_subscription(&self, msg: &Value) -> Option<SubscriptionEvent> {
+        let res = serde_json::from_value::<WsResponse>(msg.clone()).ok()?;
+        match res.event.as_str() {
+            "subscribe" => res
+                .arg
+                .and_then(|arg| arg.inst_id)
+                .map(SubscriptionEvent::Subscribed),
+            //Unknown instruments: code 60018, "Wrong URL or channel:books,instId:X-USDT doesn't exist."
+            "error" => {
+                let msg = res.msg.unwrap_or_default();
+                let invalid = msg
+                    .split_once("instId:")
+                    .and_then(|(_, rest)| rest.split([' ', ',']).next())
+                    .filter(|inst_id| !inst_id.is_empty());
+                match invalid {
+                    Some(inst_id) if res.code.as_deref() == Some("60018") => {
+                        Some(SubscriptionEvent::Invalid(inst_id.to_string()))
+                    },
+                    _ => Some(SubscriptionEvent::Failed(format!(
+                        "{} {}",
+                        res.code.unwrap_or_default(),
+                        msg
+                    ))),
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn volumes_url(&self) -> Option<String> {
+        Some("https://www.okx.com/api/v5/market/tickers?instType=SPOT".to_string())
+    }
+
+    //volCcy24h is in quote currency on spot
+    fn parse_volumes(&self, res: &Value) -> Result<HashMap<String, f64>> {
+        if res["code"].as_str() != Some("0") {
+            return Err(anyhow::anyhow!("tickers failed: {}", res["msg"]));
+        }
+        Ok(res["data"]
+            .as_array()
+            .map(|tickers| {
+                tickers
+                    .iter()
+                    .filter_map(|t| {
+                        let volume = t["volCcy24h"].as_str()?.parse::<f64>().ok()?;
+                        Some((t["instId"].as_str()?.to_string(), volume))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    //after/before are exclusive and pages hold up to 100 candles, newest first
+    fn history_candles_url(&self, inst_id: &str, from: i64, to: i64) -> Option<String> {
+        Some(format!(
+            "https://www.okx.com/api/v5/market/history-candles?instId={}&bar=1m&after={}&before={}&limit=100",
+            inst_id,
+            to + 1,
+            from - 1
+        ))
+    }
+
+    fn parse_history_candles(&self, res: &Value) -> Result<Vec<Candlestick>> {
+        if res["code"].as_str() != Some("0") {
+            return Err(anyhow::anyhow!("history-candles failed: {}", res["msg"]));
+        }
+        let mut candles: Vec<Candlestick> = res["data"]
+            .as_array()
+            .map(|rows| {
+                rows.iter()
+                    .map(|x| Okx::candle_from_row(x).get_change().get_range())
+                    .collect()
+            })
+            .unwrap_or_default();
+        candles.reverse();
+        Ok(candles)
+    }
+}
@@ -8,6 +8,8 @@ use serde_derive::{Deserialize, Serialize};
 use sha2::Sha256;
 use thiserror::Error;
 pub use time::{error::Format, format_description::well_known::Rfc3339, OffsetDateTime};
+pub mod book;
+pub mod exchanges;
 pub mod models;
 pub mod util;
 
@@ -19,10 +21,21 @@ pub struct AppConfig {
     pub mq: MessageQueue,
     pub account: Account,
     pub pushover: Option<Pushover>,
+    //Load the strategy from its own file (watched for changes by the scheduler) instead of [strategy]
+    pub strategy_file: Option<String>,
+    #[serde(default)]
     pub strategy: Strategy,
     pub exchange: Option<Exchange>,
+    pub universe: Option<Universe>,
+    pub recording: Option<Recording>,
     pub ui: Ui,
     pub server: Option<Server>,
+    pub risk: Option<Risk>,
+    pub sizing: Option<Sizing>,
+    pub regime: Option<Regime>,
+    pub search: Option<Search>,
+    pub events: Option<Events>,
+    pub alerts: Option<Alerts>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -69,6 +82,20 @@ pub struct Exchange {
     pub taker_fee: f64,
     pub maker_fee: f64,
     pub order_ttl: u32,
+    //Amend orders older than order_ttl to the latest price once, before cancelling them
+    pub reprice: Option<bool>,
+    //Keep a take-profit/stop-loss on the exchange for every position (okx algo orders)
+    pub native_exits: Option<bool>,
+    //Extra % of slippage applied to simulated taker fills when trading is disabled
+    pub paper_slippage: Option<f64>,
+    //Levels per side published from the local order book
+    pub book_depth: Option<usize>,
+    //Fetch candles missed while the producer was disconnected from the exchange REST api
+    pub backfill: Option<bool>,
+    //Backfill requests per second
+    pub backfill_rate: Option<u32>,
+    //Scheduler: build the current minute candle from the trades channel instead of tickers
+    pub trade_candles: Option<bool>,
     pub channels: Vec<ChannelSettings>,
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -76,6 +103,10 @@ pub struct ChannelSettings {
     pub name: String,
     pub topic: String,
     pub endpoint: String,
+    //Websocket connections the instruments are split across
+    pub connections: Option<usize>,
+    //Largest websocket frame accepted, in bytes (16 MiB by default)
+    pub max_frame_size: Option<usize>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -107,6 +138,49 @@ pub struct Strategy {
     pub stoploss: f32,
     pub avoid_after_stoploss: bool,
     pub sell_floor: Option<f32>,
+    //Entry/exit logic: momentum (default), mean_reversion or breakout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    //Skip tokens whose candle changes correlate above (x) with a held token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_correlation: Option<f32>,
+    //Market regime (see [regime]) adjustments while risk-off
+    //Don't buy new tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk_off_pause: Option<bool>,
+    //Hold at most (x) tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk_off_portfolio_size: Option<u32>,
+    //Stoploss (%) used instead of stoploss, when lower
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk_off_stoploss: Option<f32>,
+    //Optional exit rules, left out of the hash when unset so existing strategies keep theirs
+    //Sell when the change falls (x)% below the highest change of the round
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailing_stop: Option<f32>,
+    //Once the change reached (x)%, sell if it falls back to 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub break_even: Option<f32>,
+    //Cashout target lowered linearly down to (x)% as the timeout runs out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_cashout: Option<f32>,
+    //Optional indicator filters over the candles of the timeframe
+    //Fast EMA (half the timeframe) above the slow one (whole timeframe)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ema_crossover: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rsi: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rsi: Option<f32>,
+    //Price above the VWAP of the timeframe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub above_vwap: Option<bool>,
+    //Average true range, % of the price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_atr: Option<f32>,
+    //Last candle volume against the previous ones, in standard deviations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_vol_zscore: Option<f32>,
 }
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Ui {
@@ -119,6 +193,112 @@ pub struct Ui {
     pub balance: bool,
     pub logs: bool,
 }
+/// Account level limits. Trading halts (and the portfolio is sold) when any of them is breached
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Risk {
+    pub enable: bool,
+    //USDT lost from the session peak balance
+    pub max_drawdown: Option<f64>,
+    //% lost from the session peak balance
+    pub max_drawdown_pct: Option<f32>,
+    pub max_consecutive_stoplosses: Option<u32>,
+    //USDT held in open positions. New buys are skipped above it (doesn't halt)
+    pub max_exposure: Option<f64>,
+    //USDT lost since 00:00 UTC
+    pub daily_loss_limit: Option<f64>,
+}
+/// Instruments subscribed by the producer
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Universe {
+    //Quote currencies, ["USDT"] when unset
+    pub quotes: Option<Vec<String>>,
+    //Instrument id patterns, `*` matches any text (ex: "BTC-*")
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    //Minimum 24h volume, in quote currency
+    pub min_volume: Option<f64>,
+    //Minutes between instrument list refreshes, 0 disables it
+    pub refresh: Option<u64>,
+}
+/// Raw websocket frames saved by the producer, replayed with `producer replay <file>`
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Recording {
+    pub enable: bool,
+    //Directory of the gzipped ndjson files
+    pub path: String,
+    //Start a new file every (x) minutes, 60 when unset
+    pub rotate: Option<u64>,
+}
+/// Market regime from the trend of reference instruments, used by the strategy `risk_off_*` settings
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Regime {
+    pub enable: bool,
+    //Stored candle1m instruments, BTC-USDT and ETH-USDT when unset
+    pub instruments: Option<Vec<String>>,
+    //Minutes the change is measured over, [15, 60, 240] when unset
+    pub timeframes: Option<Vec<i64>>,
+    //Average change (%) at or above which the market is risk-on
+    pub risk_on: f32,
+    //Average change (%) at or below which the market is risk-off
+    pub risk_off: f32,
+}
+/// USDT spent per buy. Sizes are capped at `max_spendable` (`account.spendable` when unset)
+/// and the available balance
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Sizing {
+    //fixed (account.spendable), balance, volume, volatility or kelly
+    pub mode: String,
+    //balance: % of the current balance
+    pub balance_pct: Option<f64>,
+    //volume: % of the average 1m candle volume (USDT) in the timeframe
+    pub volume_pct: Option<f64>,
+    //volatility: spendable scaled by target_atr / token ATR (% of the price)
+    pub target_atr: Option<f64>,
+    //kelly: fraction of the Kelly bet, out of the current balance
+    pub kelly_fraction: Option<f64>,
+    //kelly: reports of the token needed to use its win rate, fixed size below it
+    pub kelly_min_trades: Option<usize>,
+    pub max_spendable: Option<f64>,
+}
+/// [min, max] bounds sampled by the strategy search. Parameters without bounds keep
+/// the value from the configured strategy
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Search {
+    pub timeframe: Option<(i64, i64)>,
+    pub min_change: Option<(f32, f32)>,
+    pub min_deviation: Option<(f32, f32)>,
+    pub max_deviation: Option<(f32, f32)>,
+    pub stoploss: Option<(f32, f32)>,
+    pub cashout: Option<(f32, f32)>,
+    pub sell_floor: Option<(f32, f32)>,
+    pub timeout: Option<(i64, i64)>,
+}
+/// Scheduler decisions published to redpanda (`mq`) as versioned JSON events
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Events {
+    pub enable: bool,
+    //token_selected
+    pub selections_topic: String,
+    //position_opened, position_closed
+    pub portfolio_topic: String,
+    //order_placed, order_filled
+    pub orders_topic: String,
+}
+/// Price and volume alerts. Sent with pushover (or logged) whether trading is enabled or not
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Alerts {
+    pub enable: bool,
+    //Seconds to wait before alerting again for the same token and rule
+    pub cooldown: i64,
+    //Ignore tokens with less USDT volume in the strategy timeframe
+    pub min_vol: Option<f64>,
+    //% change of the last 1m candle (up or down)
+    pub candle_change: Option<f32>,
+    //Last candle volume over (x) times the average candle volume in the timeframe
+    pub volume_spike: Option<f64>,
+    //% change since 00:00 UTC (up or down). Only known for selected tokens
+    pub change24h: Option<f32>,
+}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Server {
     pub enable: bool,
@@ -149,10 +329,19 @@ impl AppConfig {
             mq: MessageQueue::default(),
             account: Account::default(),
             pushover: None,
+            strategy_file: None,
             exchange: None,
+            universe: None,
+            recording: None,
             strategy: Strategy::default(),
             ui: Ui::default(),
             server: None,
+            risk: None,
+            sizing: None,
+            regime: None,
+            search: None,
+            events: None,
+            alerts: None,
         }
     }
     pub fn load() -> Result<Self> {
@@ -161,10 +350,13 @@ impl AppConfig {
         let config_path =
             env::var("CONFIG_PATH").unwrap_or(format!("{}/config.toml", path.display()));
         env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-        let cfg = confy::load_path(config_path).unwrap_or_else(|e| {
+        let mut cfg: AppConfig = confy::load_path(config_path).unwrap_or_else(|e| {
             log::error!("Loading default config due to:\n{}", e);
             AppConfig::default()
         });
+        if let Some(path) = &cfg.strategy_file {
+            cfg.strategy = Strategy::load(path)?;
+        }
         debug!("config loaded: {:#?}", cfg);
         Ok(cfg)
     }
@@ -209,6 +401,14 @@ impl Authentication {
             timestamp,
         })
     }
+
+    /// Hex encoded HMAC-SHA256 of a query string (Binance signed endpoints)
+    pub fn sign_query(&self, query: &str) -> Result<String, SignError> {
+        let mut mac = HmacSha256::new_from_slice(self.secret_key.as_bytes())
+            .map_err(|_| SignError::SecretKeyLength)?;
+        mac.update(query.as_bytes());
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
 }
 
 impl Default for MessageQueue {
@@ -244,6 +444,13 @@ impl Default for Exchange {
             channels: Vec::new(),
             maker_fee: 0.08,
             order_ttl: 60,
+            reprice: None,
+            native_exits: None,
+            paper_slippage: None,
+            book_depth: None,
+            backfill: None,
+            backfill_rate: None,
+            trade_candles: None,
         }
     }
 }
@@ -268,15 +475,116 @@ impl Strategy {
             stoploss: 3.0,
             avoid_after_stoploss: false,
             sell_floor: None,
+            kind: None,
+            max_correlation: None,
+            risk_off_pause: None,
+            risk_off_portfolio_size: None,
+            risk_off_stoploss: None,
+            trailing_stop: None,
+            break_even: None,
+            min_cashout: None,
+            ema_crossover: None,
+            min_rsi: None,
+            max_rsi: None,
+            above_vwap: None,
+            max_atr: None,
+            min_vol_zscore: None,
             order_type: "ioc".to_string(),
         }
     }
+    /// Load a strategy file: either the bare strategy settings or a config with a [strategy] section
+    pub fn load(path: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Section {
+            strategy: Strategy,
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Unable to read strategy file {}: {}", path, e))?;
+        let strategy = match toml::from_str::<Strategy>(&content) {
+            Ok(strategy) => strategy,
+            Err(e) => toml::from_str::<Section>(&content)
+                .map(|s| s.strategy)
+                .map_err(|_| anyhow::anyhow!("Invalid strategy file {}: {}", path, e))?,
+        };
+        debug!("strategy loaded from {}: {:#?}", path, strategy);
+        Ok(strategy)
+    }
+    /// Write the bare strategy settings, in the format read by `Strategy::load`
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = toml::to_string(&self)?;
+        std::fs::write(path, content)
+            .map_err(|e| anyhow::anyhow!("Unable to write strategy file {}: {}", path, e))
+    }
+    /// Reject settings the scheduler can't trade with
+    pub fn validate(&self) -> Result<()> {
+        let check = |ok: bool, msg: &str| -> Result<()> {
+            if ok {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Invalid strategy: {}", msg))
+            }
+        };
+        check(
+            ["market", "ioc", "limit", "post_only"].contains(&self.order_type.as_str()),
+            "order_type should be market, ioc, limit or post_only",
+        )?;
+        check(
+            self.kind.as_deref().map_or(true, |k| {
+                ["momentum", "mean_reversion", "breakout"].contains(&k)
+            }),
+            "kind should be momentum, mean_reversion or breakout",
+        )?;
+        check(self.top > 0, "top should be above 0")?;
+        check(self.portfolio_size > 0, "portfolio_size should be above 0")?;
+        check(self.timeframe > 0, "timeframe should be above 0")?;
+        check(self.timeout >= 10, "timeout should be at least 10 seconds")?;
+        check(self.cooldown >= 0, "cooldown can't be negative")?;
+        check(self.stoploss > 0.0, "stoploss should be above 0")?;
+        check(self.cashout > 0.0, "cashout should be above 0")?;
+        check(self.min_vol.is_some(), "min_vol is required")?;
+        check(self.sell_floor.is_some(), "sell_floor is required")?;
+        check(
+            self.max_correlation
+                .map_or(true, |x| (-1.0..=1.0).contains(&x)),
+            "max_correlation should be between -1 and 1",
+        )?;
+        check(
+            self.risk_off_stoploss.map_or(true, |x| x > 0.0),
+            "risk_off_stoploss should be above 0",
+        )?;
+        check(
+            self.trailing_stop.map_or(true, |x| x > 0.0),
+            "trailing_stop should be above 0",
+        )?;
+        check(
+            self.break_even.map_or(true, |x| x > 0.0),
+            "break_even should be above 0",
+        )?;
+        check(
+            self.min_cashout
+                .map_or(true, |x| x > 0.0 && x <= self.cashout),
+            "min_cashout should be above 0 and below cashout",
+        )?;
+        check(
+            self.min_rsi.unwrap_or(0.0) <= self.max_rsi.unwrap_or(100.0),
+            "min_rsi should be below max_rsi",
+        )?;
+        check(
+            self.min_deviation <= self.max_deviation,
+            "min_deviation should be below max_deviation",
+        )
+    }
     pub fn sane_defaults(&mut self) -> &mut Self {
         self.min_vol.unwrap_or((self.timeframe * 3500) as f64);
         self
     }
+    /// Hash of the settings, the same whether `hash` is already set or not
     pub fn get_hash(&self) -> String {
-        sha1_smol::Sha1::from(serde_json::to_string_pretty(&self).unwrap())
+        let strategy = Strategy {
+            hash: String::new(),
+            ..self.clone()
+        };
+        sha1_smol::Sha1::from(serde_json::to_string_pretty(&strategy).unwrap())
             .digest()
             .to_string()
     }
@@ -295,6 +603,16 @@ impl Ui {
         }
     }
 }
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            selections_topic: String::from("selections"),
+            portfolio_topic: String::from("portfolio"),
+            orders_topic: String::from("orders"),
+        }
+    }
+}
 impl Default for Server {
     fn default() -> Self {
         Self {
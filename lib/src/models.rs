@@ -126,11 +126,27 @@ pub struct Book {
 }
 
 impl Book {
+    //Match the order_entry UDT: [price, size, deprecated, num_orders]
+    fn entries(levels: &[Vec<String>]) -> Vec<Value> {
+        let field = |level: &Vec<String>, i: usize| level.get(i).cloned().unwrap_or_default();
+        levels
+            .iter()
+            .map(|l| {
+                json!({
+                "price": field(l, 0).parse::<f64>().unwrap_or(0.0),
+                "quantity": field(l, 1).parse::<f64>().unwrap_or(0.0),
+                "deprecated": field(l, 2).parse::<i32>().unwrap_or(0),
+                "num_orders": field(l, 3).parse::<i32>().unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+
     pub fn build_query(self, inst_id: &str) -> Value {
         json!({
         "instid": inst_id,
-        "asks": self.asks,
-        "bids": self.bids,
+        "asks": Book::entries(&self.asks),
+        "bids": Book::entries(&self.bids),
         "checksum": self.checksum,
         "prev_seq_id": self.prev_seq_id,
         "seq_id": self.seq_id,
@@ -178,25 +194,6 @@ impl Candlestick {
             volume: 0.0,
         }
     }
-    pub fn from_candle(msg: &Value) -> Candlestick {
-        if msg["data"] != json!(null) {
-            let x = &msg["data"][0];
-            Candlestick {
-                open: x[1].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                high: x[2].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                low: x[3].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                close: x[4].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                //vol in USD
-                volume: x[6].as_str().unwrap().parse::<f64>().unwrap_or(0.0),
-                ts: x[0].as_str().unwrap().parse::<i64>().unwrap_or(0),
-                range: 0.0,
-                change: 0.0,
-            }
-        } else {
-            Candlestick::new()
-        }
-    }
-
     pub fn get_range(mut self) -> Self {
         let range = self.high - self.low;
         self.range = format!("{:.2}", (range / self.low) * 101.00)
@@ -222,7 +219,10 @@ pub struct SubscribeMsg {
 #[derive(Serialize, Deserialize)]
 pub struct WsResponse {
     pub event: String,
-    pub arg: SubscribeArg,
+    pub arg: Option<SubscribeArg>,
+    //Only set on error events
+    pub code: Option<String>,
+    pub msg: Option<String>,
 }
 
 #[derive(Serialize, Debug, Deserialize)]
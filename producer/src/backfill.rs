@@ -0,0 +1,165 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use exchange_observer::{
+    exchanges::{Adapter, Normalized},
+    models::*,
+    AppConfig,
+};
+use log::{error, info, warn};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{mq::send_message, Client, Result};
+
+const MINUTE: i64 = 60_000;
+//candle1m keeps a day of data, older candles would expire right away
+const MAX_GAP_MINUTES: i64 = 1440;
+
+/// Minutes missing in the candles of an instrument. `from` and `to` are the first and last
+/// missing candle timestamps (ms)
+#[derive(Debug, Clone)]
+pub struct Gap {
+    pub inst_id: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Tracks the last candle of each instrument across reconnects and queues the gaps found
+pub struct Backfill {
+    last: Mutex<HashMap<String, i64>>,
+    gaps: mpsc::Sender<Gap>,
+}
+
+impl Backfill {
+    /// Start the backfill worker. Returns `None` when disabled or unsupported by the exchange
+    pub fn start(
+        adapter: Arc<dyn Adapter>,
+        client: Arc<Client>,
+        cfg: &AppConfig,
+    ) -> Option<Arc<Self>> {
+        let exchange = cfg.exchange.as_ref()?;
+        if !exchange.backfill.unwrap_or(true) {
+            return None;
+        }
+        if adapter.history_candles_url("", 0, 0).is_none() {
+            warn!("Candle backfill isn't supported on {}", adapter.name());
+            return None;
+        }
+        let partitions = cfg
+            .mq
+            .topics
+            .iter()
+            .find(|t| t.name == Channel::Candle1m.to_string())
+            .map_or(1, |t| t.partitions);
+        let rate = exchange.backfill_rate.unwrap_or(5).max(1);
+        let (tx, rx) = mpsc::channel(1024);
+        tokio::spawn(run(adapter, client, rx, partitions, rate));
+        Some(Arc::new(Self {
+            last: Mutex::new(HashMap::new()),
+            gaps: tx,
+        }))
+    }
+
+    /// Record the candle timestamp and queue the minutes missing before it
+    pub async fn check(&self, msg: &Normalized) -> Result<()> {
+        if msg.channel != Channel::Candle1m {
+            return Ok(());
+        }
+        let ts = serde_json::from_str::<Candlestick>(&msg.data)?.ts;
+        let gap = {
+            let mut last = self.last.lock().await;
+            match last.get(&msg.inst_id).copied() {
+                //Updates of the current minute and late candles
+                Some(prev) if ts <= prev => None,
+                Some(prev) => {
+                    last.insert(msg.inst_id.clone(), ts);
+                    (ts - prev > MINUTE).then(|| Gap {
+                        inst_id: msg.inst_id.clone(),
+                        from: (prev + MINUTE).max(ts - MAX_GAP_MINUTES * MINUTE),
+                        to: ts - MINUTE,
+                    })
+                },
+                None => {
+                    last.insert(msg.inst_id.clone(), ts);
+                    None
+                },
+            }
+        };
+        if let Some(gap) = gap {
+            info!(
+                "[{}] Missing {} candles, backfilling",
+                gap.inst_id,
+                (gap.to - gap.from) / MINUTE + 1
+            );
+            if self.gaps.try_send(gap).is_err() {
+                warn!("Backfill queue is full, dropping gap of {}", msg.inst_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fetch queued gaps one request at a time, at most `rate` requests per second
+async fn run(
+    adapter: Arc<dyn Adapter>,
+    client: Arc<Client>,
+    mut gaps: mpsc::Receiver<Gap>,
+    partitions: i32,
+    rate: u32,
+) {
+    let http = reqwest::Client::new();
+    let interval = Duration::from_millis(1000 / rate as u64);
+    let partition_count = Mutex::new(HashMap::from([(Channel::Candle1m.to_string(), 0)]));
+    while let Some(gap) = gaps.recv().await {
+        let mut to = gap.to;
+        let mut sent = 0;
+        while to >= gap.from {
+            tokio::time::sleep(interval).await;
+            let candles = match fetch(adapter.as_ref(), &http, &gap.inst_id, gap.from, to).await {
+                Ok(candles) => candles,
+                Err(e) => {
+                    error!("[{}] Unable to backfill candles: {}", gap.inst_id, e);
+                    break;
+                },
+            };
+            let oldest = match candles.first() {
+                Some(candle) => candle.ts,
+                None => break,
+            };
+            for candle in candles
+                .into_iter()
+                .filter(|c| c.ts >= gap.from && c.ts <= to)
+            {
+                let msg = Normalized {
+                    channel: Channel::Candle1m,
+                    inst_id: gap.inst_id.clone(),
+                    data: serde_json::to_string(&candle).unwrap_or_default(),
+                };
+                if let Err(e) =
+                    send_message(adapter.name(), msg, &partition_count, client.clone(), true).await
+                {
+                    error!("[{}] Unable to send backfilled candle: {}", gap.inst_id, e);
+                }
+                sent += 1;
+                let mut map = partition_count.lock().await;
+                let p = map.entry(Channel::Candle1m.to_string()).or_insert(0);
+                *p = (*p + 1) % partitions.max(1);
+            }
+            to = oldest - MINUTE;
+        }
+        info!("[{}] Backfilled {} candles", gap.inst_id, sent);
+    }
+}
+
+async fn fetch(
+    adapter: &dyn Adapter,
+    http: &reqwest::Client,
+    inst_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Candlestick>> {
+    let url = adapter
+        .history_candles_url(inst_id, from, to)
+        .ok_or_else(|| anyhow::anyhow!("history candles aren't supported"))?;
+    let res = http.get(url).send().await?.text().await?;
+    adapter.parse_history_candles(&serde_json::from_str(&res)?)
+}
@@ -1,20 +1,42 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
-use anyhow::Result;
-use exchange_observer::{models::Channel, util::Elapsed, AppConfig, ChannelSettings};
+use anyhow::{anyhow, Result};
+use exchange_observer::{
+    book::BookError,
+    exchanges::{self, Adapter},
+    models::Channel,
+    util::Elapsed,
+    AppConfig,
+};
 use futures_util::{SinkExt, StreamExt};
 use log::{error, info, warn};
 use rskafka::client::{Client, ClientBuilder};
 use serde_json::Value;
 pub use stats::*;
-use tokio::sync::{watch, Mutex};
+use tokio::sync::{mpsc, Mutex};
 use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::ws::WsStream;
+use crate::{
+    backfill::Backfill,
+    recorder::{Recorder, Replay},
+    ws::{Shard, Subscription, WsStream},
+};
+pub mod backfill;
 pub mod mq;
+pub mod recorder;
 pub mod stats;
+pub mod universe;
 pub mod ws;
 
+const RECONNECT_SECS: u64 = 5;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cfg: AppConfig = AppConfig::load()?;
@@ -30,35 +52,73 @@ async fn main() -> Result<()> {
 
     mq::create_topics(&client, &cfg).await?;
 
-    let channels = cfg.exchange.as_ref().unwrap().channels.clone();
+    let exchange = cfg.exchange.as_ref().unwrap();
+    let adapter: Arc<dyn Adapter> = Arc::from(exchanges::from_config(exchange)?);
+    info!("Using {} exchange adapter", adapter.name());
+
+    //producer replay <file> [--speed <x>|max]
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        return Replay::from_args(&args[2..])?
+            .run(adapter, client, &cfg)
+            .await;
+    }
+
+    let channels = exchange.channels.clone();
+    //Candles missed while disconnected are fetched from the REST api
+    let backfill = Backfill::start(adapter.clone(), client.clone(), &cfg);
+    let recorder = Recorder::start(&cfg, adapter.name())?;
+
+    let universe = cfg.universe.clone().unwrap_or_default();
+    let inst_ids = universe::fetch(adapter.clone(), &universe).await?;
+    info!("Tracking {} instruments", inst_ids.len());
+
+    //Instruments of each channel are split across `connections` websockets
+    let shards: Vec<Shard> = channels
+        .iter()
+        .flat_map(|channel| ws::build_shards(channel, &inst_ids))
+        .collect();
+    //Listed/delisted instruments are (un)subscribed on the live shards
+    tokio::spawn(universe::refresh(
+        adapter.clone(),
+        universe,
+        shards.clone(),
+        inst_ids,
+    ));
 
-    let (disconnect_tx, mut disconnect_rx) = tokio::sync::mpsc::channel(channels.len());
+    let (disconnect_tx, mut disconnect_rx) = tokio::sync::mpsc::channel(shards.len().max(1));
 
-    for channel in &channels {
+    for shard in shards {
         let disconnect_tx = disconnect_tx.clone();
-        let channel = channel.clone();
         let cfg = cfg.clone();
 
         tokio::spawn(handle_connection(
+            adapter.clone(),
             client.clone(),
-            channel,
+            backfill.clone(),
+            recorder.clone(),
+            shard,
             cfg,
             disconnect_tx,
         ));
     }
 
-    while let Some(disconnected_channel) = disconnect_rx.recv().await {
+    //Only the disconnected shard is resubscribed
+    while let Some(disconnected_shard) = disconnect_rx.recv().await {
         error!(
-            "Channel {:?} disconnected. Trying to reconnect...",
-            disconnected_channel
+            "Shard {} disconnected. Trying to reconnect...",
+            disconnected_shard.name()
         );
 
         let disconnect_tx = disconnect_tx.clone();
         let cfg = cfg.clone();
 
         tokio::spawn(handle_connection(
+            adapter.clone(),
             client.clone(),
-            disconnected_channel,
+            backfill.clone(),
+            recorder.clone(),
+            disconnected_shard,
             cfg,
             disconnect_tx,
         ));
@@ -68,42 +128,72 @@ async fn main() -> Result<()> {
 }
 
 async fn handle_connection(
+    adapter: Arc<dyn Adapter>,
     client: Arc<Client>,
-    channel: ChannelSettings,
+    backfill: Option<Arc<Backfill>>,
+    recorder: Option<Arc<Recorder>>,
+    shard: Shard,
     cfg: AppConfig,
-    disconnect_tx: tokio::sync::mpsc::Sender<ChannelSettings>,
+    disconnect_tx: tokio::sync::mpsc::Sender<Shard>,
 ) -> Result<()> {
     loop {
-        match ws::connect_and_subscribe(channel.clone()).await {
+        match ws::connect_and_subscribe(adapter.clone(), &shard).await {
             Ok(ws_stream) => {
-                if run(client.clone(), ws_stream, &cfg).await.is_err() {
-                    warn!("channel {} Disconnected", channel.name.to_string());
-                    break;
+                match run(
+                    adapter.clone(),
+                    client.clone(),
+                    backfill.clone(),
+                    recorder.clone(),
+                    ws_stream,
+                    &shard,
+                    &cfg,
+                )
+                .await
+                {
+                    Ok(()) => warn!("Shard {} stream closed", shard.name()),
+                    Err(e) => {
+                        warn!("Shard {} disconnected: {}", shard.name(), e);
+                        break;
+                    },
                 }
             },
-            Err(e) => {
-                error!("Failed to connect to channel {:?}: {:?}", channel, e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            },
+            Err(e) => error!("Failed to connect shard {}: {:?}", shard.name(), e),
         }
+        tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_SECS)).await;
     }
-    disconnect_tx.send(channel).await?;
+    //Don't hit the exchange connection limits when the shard keeps failing
+    tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_SECS)).await;
+    disconnect_tx.send(shard).await?;
 
     Ok(())
 }
 
-async fn run(client: Arc<Client>, mut ws: WsStream, cfg: &AppConfig) -> Result<()> {
-    let exchange = String::from("Okx");
+async fn run(
+    adapter: Arc<dyn Adapter>,
+    client: Arc<Client>,
+    backfill: Option<Arc<Backfill>>,
+    recorder: Option<Arc<Recorder>>,
+    mut ws: WsStream,
+    shard: &Shard,
+    cfg: &AppConfig,
+) -> Result<()> {
     let inc = Arc::new(Mutex::new(0));
-    let (tx, mut rx) = watch::channel(false);
+    let subscription = Mutex::new(Subscription::new(&shard.inst_ids().await));
+    //Set on rejected subscriptions to drop the connection and resubscribe the shard
+    let failed = AtomicBool::new(false);
+    //Outgoing messages (keep-alive pings, book resubscriptions) share the write half
+    let (tx, mut rx) = mpsc::channel::<Message>(32);
     let cooldowns = Cooldowns::default();
+    shard.set_writer(Some(tx.clone())).await;
     tokio::spawn(async move {
-        while rx.changed().await.is_ok() {
-            info!("Sending keep-alive ping");
-            ws.write
-                .send(Message::Ping(Vec::new()))
-                .await
-                .expect("Failed to send message");
+        while let Some(msg) = rx.recv().await {
+            if let Message::Ping(_) = msg {
+                info!("Sending keep-alive ping");
+            }
+            if let Err(e) = ws.write.send(msg).await {
+                error!("Unable to send message: {}", e);
+                break;
+            }
         }
     });
     let partition_count = Arc::new(Mutex::new(HashMap::from([
@@ -114,7 +204,11 @@ async fn run(client: Arc<Client>, mut ws: WsStream, cfg: &AppConfig) -> Result<(
     ])));
 
     //Send received websocket messages to corresponding queues
-    let read_future = ws.read.for_each(|message| async {
+    //Stop reading once the shard has to be resubscribed
+    let read = ws
+        .read
+        .take_while(|_| futures::future::ready(!failed.load(Ordering::Relaxed)));
+    let read_future = read.for_each(|message| async {
         let start = Instant::now();
         let data = match message {
             Ok(m) => m.into_data(),
@@ -123,9 +217,12 @@ async fn run(client: Arc<Client>, mut ws: WsStream, cfg: &AppConfig) -> Result<(
                 Vec::new()
             },
         };
+        if let Some(recorder) = recorder.as_deref().filter(|_| !data.is_empty()) {
+            recorder.record(&shard.channel.name, &data);
+        }
 
         if cooldowns.ping.lock().await.elapsed().as_millis() >= 25000 {
-            if let Err(e) = tx.send(true) {
+            if let Err(e) = tx.send(Message::Ping(Vec::new())).await {
                 error!("{}", e);
             } else {
                 *cooldowns.ping.lock().await = Instant::now();
@@ -134,9 +231,34 @@ async fn run(client: Arc<Client>, mut ws: WsStream, cfg: &AppConfig) -> Result<(
 
         match serde_json::from_str::<Value>(&String::from_utf8_lossy(&data)) {
             Ok(res) => {
-                ws::process_message(&exchange, &partition_count, client.clone(), &res)
-                    .await
-                    .unwrap();
+                let invalid = {
+                    let mut subscription = subscription.lock().await;
+                    if let Err(e) = subscription.check(adapter.as_ref(), &res) {
+                        error!("[{}] {}", shard.name(), e);
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    std::mem::take(&mut subscription.invalid)
+                };
+                if !invalid.is_empty() {
+                    shard.forget(&invalid).await;
+                }
+                if let Err(e) = ws::process_message(
+                    adapter.as_ref(),
+                    &partition_count,
+                    client.clone(),
+                    backfill.as_deref(),
+                    &res,
+                )
+                .await
+                {
+                    match e.downcast_ref::<BookError>() {
+                        Some(book_error) => {
+                            warn!("{}. Resubscribing", book_error);
+                            resubscribe(adapter.as_ref(), &tx, book_error.inst_id()).await;
+                        },
+                        None => error!("Unable to process message: {:?}", e),
+                    }
+                }
             },
             Err(e) => {
                 warn!("Deserialization error: {}", e);
@@ -153,5 +275,22 @@ async fn run(client: Arc<Client>, mut ws: WsStream, cfg: &AppConfig) -> Result<(
         }
     });
     read_future.await;
+    shard.set_writer(None).await;
+    if failed.load(Ordering::Relaxed) {
+        return Err(anyhow!("subscription failed"));
+    }
     Ok(())
 }
+
+async fn resubscribe(adapter: &dyn Adapter, tx: &mpsc::Sender<Message>, inst_id: &str) {
+    match ws::build_resubscribe(adapter, inst_id) {
+        Ok(msgs) => {
+            for msg in msgs {
+                if let Err(e) = tx.send(Message::Text(msg + "\n")).await {
+                    error!("Unable to resubscribe {}: {}", inst_id, e);
+                }
+            }
+        },
+        Err(e) => error!("Unable to build resubscribe for {}: {}", inst_id, e),
+    }
+}
@@ -1,13 +1,13 @@
 use std::collections::{BTreeMap, HashMap};
 
-use exchange_observer::{models::*, AppConfig};
+use exchange_observer::{exchanges::Normalized, models::*, AppConfig};
 use rskafka::{
     client::{partition::Compression, Client},
     record::Record,
 };
 use time::OffsetDateTime;
 
-use crate::{info, warn, Arc, Mutex, Result, Value};
+use crate::{info, warn, Arc, Mutex, Result};
 
 pub async fn produce(
     topic: Channel,
@@ -29,18 +29,24 @@ pub fn build_record(
     inst_id: &[u8],
     data: &str,
     partition: String,
+    backfill: bool,
 ) -> Record {
+    let mut headers = BTreeMap::from([
+        ("Exchange".to_owned(), exchange.as_bytes().to_vec()),
+        (
+            "Channel".to_owned(),
+            channel.to_string().as_bytes().to_vec(),
+        ),
+        ("Partition".to_owned(), partition.as_bytes().to_vec()),
+    ]);
+    //Fetched from the REST api after a disconnection
+    if backfill {
+        headers.insert("Backfill".to_owned(), b"true".to_vec());
+    }
     Record {
         key: Some(inst_id.to_vec()),
         value: Some(data.as_bytes().to_vec()),
-        headers: BTreeMap::from([
-            ("Exchange".to_owned(), exchange.as_bytes().to_vec()),
-            (
-                "Channel".to_owned(),
-                channel.to_string().as_bytes().to_vec(),
-            ),
-            ("Partition".to_owned(), partition.as_bytes().to_vec()),
-        ]),
+        headers,
         timestamp: OffsetDateTime::now_utc(),
     }
 }
@@ -66,35 +72,26 @@ pub async fn create_topics(client: &Client, cfg: &AppConfig) -> Result<()> {
 
 pub async fn send_message(
     exchange: &str,
-    channel: Channel,
-    data: &Value,
+    msg: Normalized,
     partition_count: &Mutex<HashMap<String, i32>>,
     client: Arc<Client>,
-    inst_id_bytes: Vec<u8>,
+    backfill: bool,
 ) -> Result<()> {
-    let data = match channel {
-        Channel::Tickers => serde_json::to_string(&serde_json::from_str::<Ticker>(
-            &data["data"][0].to_string(),
-        )?)?,
-        Channel::Trades => serde_json::to_string(&serde_json::from_str::<Trade>(
-            &data["data"][0].to_string(),
-        )?)?,
-        Channel::Books => {
-            serde_json::to_string(&serde_json::from_str::<Book>(&data["data"][0].to_string())?)?
-        },
-        Channel::Candle1m => {
-            serde_json::to_string(&Candlestick::from_candle(data).get_change().get_range())?
-        },
-    };
-
     let p = {
         let map = partition_count.lock().await;
-        *map.get(&channel.to_string()).unwrap()
+        *map.get(&msg.channel.to_string()).unwrap()
     };
 
     //Save the partition in a header (dont know how to retrieve afterwards without this)
-    let record = build_record(exchange, channel, &inst_id_bytes, &data, p.to_string());
-    produce(channel, p, client, record)
+    let record = build_record(
+        exchange,
+        msg.channel,
+        msg.inst_id.as_bytes(),
+        &msg.data,
+        p.to_string(),
+        backfill,
+    );
+    produce(msg.channel, p, client, record)
         .await
         .expect("failed to produce message");
     Ok(())
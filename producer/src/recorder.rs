@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use exchange_observer::{exchanges::Adapter, models::Channel, AppConfig, Recording};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::mpsc, task};
+
+use crate::{update_partition_count, ws, Arc, Client, Cooldowns, HashMap, Mutex, Result};
+
+/// Raw websocket frame with its receive timestamp (ms)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Frame {
+    pub ts: i64,
+    pub channel: String,
+    pub data: String,
+}
+
+/// Saves received frames to gzipped ndjson files, rotated every `rotate` minutes
+pub struct Recorder {
+    frames: mpsc::Sender<Frame>,
+}
+
+impl Recorder {
+    /// Start the writer. Returns `None` when recording is disabled
+    pub fn start(cfg: &AppConfig, exchange: &str) -> Result<Option<Arc<Self>>> {
+        let recording = match cfg.recording.as_ref().filter(|r| r.enable) {
+            Some(recording) => recording.clone(),
+            None => return Ok(None),
+        };
+        std::fs::create_dir_all(&recording.path)?;
+        let (tx, rx) = mpsc::channel(65536);
+        let exchange = exchange.to_string();
+        task::spawn_blocking(move || {
+            if let Err(e) = write(rx, &recording, &exchange) {
+                error!("Recording stopped: {}", e);
+            }
+        });
+        Ok(Some(Arc::new(Self { frames: tx })))
+    }
+
+    pub fn record(&self, channel: &str, data: &[u8]) {
+        let frame = Frame {
+            ts: Utc::now().timestamp_millis(),
+            channel: channel.to_string(),
+            data: String::from_utf8_lossy(data).to_string(),
+        };
+        if self.frames.try_send(frame).is_err() {
+            warn!("Recording queue is full, dropping frame");
+        }
+    }
+}
+
+fn write(mut frames: mpsc::Receiver<Frame>, cfg: &Recording, exchange: &str) -> Result<()> {
+    let rotate = Duration::from_secs(cfg.rotate.unwrap_or(60).max(1) * 60);
+    let mut file: Option<(GzEncoder<BufWriter<File>>, Instant)> = None;
+    let mut flushed = Instant::now();
+    while let Some(frame) = frames.blocking_recv() {
+        if file
+            .as_ref()
+            .map_or(true, |(_, opened)| opened.elapsed() >= rotate)
+        {
+            if let Some((encoder, _)) = file.take() {
+                encoder.finish()?.flush()?;
+            }
+            let path = Path::new(&cfg.path).join(format!(
+                "{}-{}.ndjson.gz",
+                exchange,
+                Utc::now().format("%Y%m%d-%H%M%S")
+            ));
+            info!("Recording market data to {}", path.display());
+            let encoder =
+                GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+            file = Some((encoder, Instant::now()));
+        }
+        if let Some((encoder, _)) = file.as_mut() {
+            serde_json::to_writer(&mut *encoder, &frame)?;
+            encoder.write_all(b"\n")?;
+            //Keep the file readable up to the last second when the producer is killed
+            if flushed.elapsed() >= Duration::from_secs(1) {
+                encoder.flush()?;
+                flushed = Instant::now();
+            }
+        }
+    }
+    if let Some((encoder, _)) = file {
+        encoder.finish()?.flush()?;
+    }
+    Ok(())
+}
+
+/// Feed a recording through the websocket message path into the message queue
+pub struct Replay {
+    path: String,
+    //Multiplier of the recorded pace, `None` to send frames as fast as possible
+    speed: Option<f64>,
+}
+
+impl Replay {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let usage = || anyhow!("Usage: producer replay <file> [--speed <x>|max]");
+        let mut args = args.iter();
+        let path = args.next().ok_or_else(usage)?.to_string();
+        let mut speed = Some(1.0);
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("Missing value for argument {}", arg))?;
+            match arg.as_str() {
+                "--speed" if value == "max" => speed = None,
+                "--speed" => {
+                    let x = value.trim_end_matches('x').parse::<f64>()?;
+                    if x <= 0.0 {
+                        return Err(anyhow!("--speed should be above 0"));
+                    }
+                    speed = Some(x);
+                },
+                _ => return Err(usage()),
+            }
+        }
+        Ok(Self { path, speed })
+    }
+
+    pub async fn run(
+        &self,
+        adapter: Arc<dyn Adapter>,
+        client: Arc<Client>,
+        cfg: &AppConfig,
+    ) -> Result<()> {
+        info!(
+            "Replaying {} at {} speed",
+            self.path,
+            self.speed.map_or("max".to_string(), |x| format!("{}x", x))
+        );
+        let (tx, mut rx) = mpsc::channel(1024);
+        let path = self.path.clone();
+        let reader = task::spawn_blocking(move || read(&path, tx));
+
+        let partition_count = Mutex::new(HashMap::from([
+            (Channel::Candle1m.to_string(), 0),
+            (Channel::Tickers.to_string(), 0),
+            (Channel::Books.to_string(), 0),
+            (Channel::Trades.to_string(), 0),
+        ]));
+        let cooldowns = Cooldowns::default();
+        //Recorded and replay clocks, frames are sent relative to the first one
+        let mut start: Option<(i64, tokio::time::Instant)> = None;
+        let mut count = 0;
+        while let Some(frame) = rx.recv().await {
+            match (self.speed, start) {
+                (Some(speed), Some((first, started))) => {
+                    let offset = (frame.ts - first).max(0) as f64 / speed;
+                    tokio::time::sleep_until(started + Duration::from_millis(offset as u64)).await;
+                },
+                (_, None) => start = Some((frame.ts, tokio::time::Instant::now())),
+                _ => {},
+            }
+            let res = match serde_json::from_str::<Value>(&frame.data) {
+                Ok(res) => res,
+                Err(_) => continue,
+            };
+            //Recordings started mid-stream can't rebuild the order books until the next snapshot
+            if let Err(e) = ws::process_message(
+                adapter.as_ref(),
+                &partition_count,
+                client.clone(),
+                None,
+                &res,
+            )
+            .await
+            {
+                warn!("Unable to process {} frame: {}", frame.channel, e);
+            }
+            update_partition_count(&cooldowns, &partition_count, cfg).await;
+            count += 1;
+        }
+        reader.await??;
+        info!("Replayed {} frames from {}", count, self.path);
+        Ok(())
+    }
+}
+
+fn read(path: &str, frames: mpsc::Sender<Frame>) -> Result<()> {
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+    for line in reader.lines() {
+        //Files of a killed producer end without the gzip trailer
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Stopped reading {}: {}", path, e);
+                break;
+            },
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if frames.blocking_send(serde_json::from_str(&line)?).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
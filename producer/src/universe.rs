@@ -0,0 +1,156 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use crypto_market_type::MarketType;
+use crypto_markets::fetch_symbols;
+use exchange_observer::{exchanges::Adapter, Universe};
+use log::{error, info, warn};
+use tokio::task;
+
+use crate::{ws::Shard, Result};
+
+/// Exchange instruments matching the configured quotes, patterns and minimum volume
+pub async fn fetch(adapter: Arc<dyn Adapter>, cfg: &Universe) -> Result<Vec<String>> {
+    let name = adapter.name();
+    let symbols = task::spawn_blocking(move || fetch_symbols(name, MarketType::Spot)).await??;
+    let quotes = cfg
+        .quotes
+        .clone()
+        .unwrap_or_else(|| vec!["USDT".to_string()]);
+    let mut inst_ids: Vec<String> = adapter
+        .parse_symbols(symbols)
+        .into_iter()
+        .filter(|inst_id| {
+            let quote = inst_id.rsplit('-').next().unwrap_or_default();
+            quotes.iter().any(|q| q.eq_ignore_ascii_case(quote))
+                && cfg
+                    .include
+                    .as_ref()
+                    .map_or(true, |p| p.iter().any(|p| matches(p, inst_id)))
+                && !cfg
+                    .exclude
+                    .as_ref()
+                    .map_or(false, |p| p.iter().any(|p| matches(p, inst_id)))
+        })
+        .collect();
+
+    if let Some(min_volume) = cfg.min_volume.filter(|v| *v > 0.0) {
+        match volumes(adapter.as_ref()).await {
+            Ok(volumes) => inst_ids.retain(|inst_id| {
+                volumes
+                    .get(inst_id)
+                    .map_or(false, |volume| *volume >= min_volume)
+            }),
+            Err(e) => warn!("Unable to filter instruments by volume: {}", e),
+        }
+    }
+    inst_ids.sort();
+    inst_ids.dedup();
+    Ok(inst_ids)
+}
+
+async fn volumes(adapter: &dyn Adapter) -> Result<HashMap<String, f64>> {
+    let url = adapter
+        .volumes_url()
+        .ok_or_else(|| anyhow!("24h volumes aren't supported on {}", adapter.name()))?;
+    let res = reqwest::get(url).await?.text().await?;
+    adapter.parse_volumes(&serde_json::from_str(&res)?)
+}
+
+/// Case insensitive match, `*` matches any text
+fn matches(pattern: &str, inst_id: &str) -> bool {
+    let (pattern, inst_id) = (pattern.to_uppercase(), inst_id.to_uppercase());
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return pattern == inst_id;
+    }
+    if !inst_id.starts_with(first) || !inst_id[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &inst_id[first.len()..inst_id.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Fetch the universe every `refresh` minutes (60 by default). Listed instruments are
+/// subscribed on the least loaded shard of each channel and delisted ones unsubscribed,
+/// on the live connections
+pub async fn refresh(
+    adapter: Arc<dyn Adapter>,
+    cfg: Universe,
+    shards: Vec<Shard>,
+    inst_ids: Vec<String>,
+) {
+    let minutes = cfg.refresh.unwrap_or(60);
+    if minutes == 0 {
+        return;
+    }
+    let mut current: HashSet<String> = inst_ids.into_iter().collect();
+    loop {
+        tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+        let latest: HashSet<String> = match fetch(adapter.clone(), &cfg).await {
+            Ok(latest) if !latest.is_empty() => latest.into_iter().collect(),
+            Ok(_) => {
+                warn!("No instruments found, keeping the current universe");
+                continue;
+            },
+            Err(e) => {
+                error!("Unable to refresh the instrument universe: {}", e);
+                continue;
+            },
+        };
+        let mut listed: Vec<String> = latest.difference(&current).cloned().collect();
+        let delisted: HashSet<String> = current.difference(&latest).cloned().collect();
+        if listed.is_empty() && delisted.is_empty() {
+            continue;
+        }
+        listed.sort();
+        info!(
+            "Universe refreshed: {} listed {:?}, {} delisted {:?}",
+            listed.len(),
+            listed,
+            delisted.len(),
+            delisted
+        );
+
+        for shard in shards.iter() {
+            if let Err(e) = shard.remove(adapter.as_ref(), &delisted).await {
+                error!(
+                    "[{}] Unable to unsubscribe instruments: {}",
+                    shard.name(),
+                    e
+                );
+            }
+        }
+        if !listed.is_empty() {
+            //Shards are built channel by channel
+            let mut channels: Vec<&str> = shards.iter().map(|s| s.channel.name.as_str()).collect();
+            channels.dedup();
+            for channel in channels {
+                let mut target: Option<(&Shard, usize)> = None;
+                for shard in shards.iter().filter(|s| s.channel.name == channel) {
+                    let len = shard.inst_ids().await.len();
+                    if target.map_or(true, |(_, min)| len < min) {
+                        target = Some((shard, len));
+                    }
+                }
+                if let Some((shard, _)) = target {
+                    if let Err(e) = shard.add(adapter.as_ref(), &listed).await {
+                        error!("[{}] Unable to subscribe instruments: {}", shard.name(), e);
+                    }
+                }
+            }
+        }
+        current = latest;
+    }
+}
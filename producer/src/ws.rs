@@ -1,60 +1,154 @@
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crypto_market_type::MarketType;
-use crypto_markets::fetch_symbols;
-use exchange_observer::{models::*, ChannelSettings};
+use anyhow::anyhow;
+use exchange_observer::{
+    exchanges::{Adapter, SubscriptionEvent},
+    models::*,
+    ChannelSettings,
+};
 use futures_util::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use log::info;
+use log::{debug, info, warn};
 use native_tls::TlsConnector;
-use serde_json::{json, Value};
-use tokio::{net::TcpStream, sync::Mutex, task};
+use serde_json::Value;
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, Mutex},
+};
 use tokio_tungstenite::{
     connect_async_tls_with_config,
     tungstenite::protocol::{Message, WebSocketConfig},
     Connector, MaybeTlsStream, WebSocketStream,
 };
 
-use crate::{mq::send_message, Client, Result};
+use crate::{backfill::Backfill, mq::send_message, Client, Result};
 
 //const UPLINK_LIMIT: (NonZeroU32, std::time::Duration) =
 //    (nonzero!(240u32), std::time::Duration::from_secs(3600));
-const WS_FRAME_SIZE: usize = 4096;
+//okx books snapshots (400 levels per side) are far above 4 KiB, see `max_frame_size`
+const WS_FRAME_SIZE: usize = 16 << 20;
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct WsStream {
     pub read: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
     pub write: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
 }
 
-pub fn build_args(channel: Channel, pairs: &[String]) -> Vec<SubscribeArg> {
-    let mut args: Vec<SubscribeArg> = Vec::new();
-    for i in pairs.iter() {
-        let arg = match channel {
-            Channel::Tickers | Channel::Candle1m => SubscribeArg {
-                channel: channel.to_string(),
-                inst_type: Some("SPOT".to_string()),
-                inst_id: Some(i.to_string()),
-            },
-            Channel::Trades | Channel::Books => SubscribeArg {
-                channel: channel.to_string(),
-                inst_type: None,
-                inst_id: Some(i.to_string()),
-            },
+/// Instruments of a channel handled by one websocket connection. Clones share the instrument
+/// list and the writer of the live connection, so the universe refresh can update them
+#[derive(Debug, Clone)]
+pub struct Shard {
+    pub channel: ChannelSettings,
+    pub id: usize,
+    inst_ids: Arc<Mutex<Vec<String>>>,
+    //Outgoing messages of the live connection, `None` while disconnected
+    writer: Arc<Mutex<Option<mpsc::Sender<Message>>>>,
+}
+
+impl Shard {
+    pub fn new(channel: ChannelSettings, id: usize, inst_ids: Vec<String>) -> Self {
+        Self {
+            channel,
+            id,
+            inst_ids: Arc::new(Mutex::new(inst_ids)),
+            writer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}#{}", self.channel.name, self.id)
+    }
+
+    pub fn kind(&self) -> Result<Channel> {
+        Channel::from_str(&self.channel.name)
+            .map_err(|_| anyhow!("Unknown channel: {}", self.channel.name))
+    }
+
+    pub async fn inst_ids(&self) -> Vec<String> {
+        self.inst_ids.lock().await.clone()
+    }
+
+    pub async fn set_writer(&self, writer: Option<mpsc::Sender<Message>>) {
+        *self.writer.lock().await = writer;
+    }
+
+    /// Add instruments, subscribing them when the connection is up
+    pub async fn add(&self, adapter: &dyn Adapter, inst_ids: &[String]) -> Result<()> {
+        self.inst_ids.lock().await.extend(inst_ids.iter().cloned());
+        self.send(adapter.build_subscribe(self.kind()?, inst_ids)?)
+            .await
+    }
+
+    /// Stop tracking instruments the exchange rejected, nothing to unsubscribe
+    pub async fn forget(&self, inst_ids: &[String]) {
+        self.inst_ids.lock().await.retain(|i| !inst_ids.contains(i));
+    }
+
+    /// Remove the instruments held by the shard, unsubscribing them when the connection is up
+    pub async fn remove(&self, adapter: &dyn Adapter, inst_ids: &HashSet<String>) -> Result<()> {
+        let removed: Vec<String> = {
+            let mut held = self.inst_ids.lock().await;
+            let (removed, kept): (Vec<String>, Vec<String>) =
+                held.drain(..).partition(|i| inst_ids.contains(i));
+            *held = kept;
+            removed
         };
-        args.push(arg);
+        if removed.is_empty() {
+            return Ok(());
+        }
+        self.send(adapter.build_unsubscribe(self.kind()?, &removed)?)
+            .await
     }
-    args
+
+    //Disconnected shards pick up the changes on reconnect
+    async fn send(&self, msgs: Vec<String>) -> Result<()> {
+        if let Some(writer) = self.writer.lock().await.as_ref() {
+            for msg in msgs {
+                writer.send(Message::Text(msg + "\n")).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split the instruments across the connections configured for the channel
+pub fn build_shards(channel: &ChannelSettings, inst_ids: &[String]) -> Vec<Shard> {
+    if inst_ids.is_empty() {
+        warn!("No instruments found for channel {}", channel.name);
+    }
+    let connections = channel
+        .connections
+        .unwrap_or(1)
+        .clamp(1, inst_ids.len().max(1));
+    let size = ((inst_ids.len() + connections - 1) / connections).max(1);
+    let shards: Vec<Shard> = inst_ids
+        .chunks(size)
+        .enumerate()
+        .map(|(id, inst_ids)| Shard::new(channel.clone(), id, inst_ids.to_vec()))
+        .collect();
+    info!(
+        "Channel {}: {} instruments across {} connections",
+        channel.name,
+        inst_ids.len(),
+        shards.len()
+    );
+    shards
 }
 
-pub async fn connect_and_subscribe(channel: ChannelSettings) -> Result<WsStream> {
+pub async fn connect_and_subscribe(adapter: Arc<dyn Adapter>, shard: &Shard) -> Result<WsStream> {
     let ws_config = WebSocketConfig {
-        max_frame_size: Some(WS_FRAME_SIZE),
+        max_frame_size: Some(shard.channel.max_frame_size.unwrap_or(WS_FRAME_SIZE)),
         ..Default::default()
     };
 
-    let url = url::Url::parse(&channel.endpoint)?;
+    let url = url::Url::parse(&shard.channel.endpoint)?;
 
     let (ws_stream, _response) = connect_async_tls_with_config(
         &url,
@@ -65,48 +159,101 @@ pub async fn connect_and_subscribe(channel: ChannelSettings) -> Result<WsStream>
 
     let (mut write, read) = ws_stream.split();
 
-    let subscribe_msgs =
-        task::spawn_blocking(move || build_subscribe(channel.name.clone())).await??;
+    let inst_ids = shard.inst_ids().await;
+    let subscribe_msgs = build_subscribe(adapter.as_ref(), shard, &inst_ids)?;
+    info!(
+        "Sending {} subscriptions ({} instruments) to {} on endpoint {}",
+        subscribe_msgs.len(),
+        inst_ids.len(),
+        shard.name(),
+        url,
+    );
     for msg in subscribe_msgs {
-        info!(
-            "Sending subscription to channel {} on endpoint {}",
-            msg.args[0].channel, url,
-        );
-        let msg_cr = serde_json::to_string(&msg)? + "\n";
+        let msg_cr = msg + "\n";
         write.send(Message::Text(msg_cr)).await?;
     }
 
     Ok(WsStream { read, write })
 }
 
-pub fn build_subscribe(channel: String) -> Result<Vec<SubscribeMsg>> {
-    let mut msgs = Vec::new();
-    let symbols = parse_symbols(fetch_symbols("okx", MarketType::Spot)?);
-    let channel = Channel::from_str(&channel).unwrap();
+pub fn build_subscribe(
+    adapter: &dyn Adapter,
+    shard: &Shard,
+    inst_ids: &[String],
+) -> Result<Vec<String>> {
+    let channel = shard.kind()?;
     info!("Building subscribe for channel {:?}", channel);
-    let args_ws = build_args(channel, &symbols);
-    let subscribe_msg = SubscribeMsg {
-        op: String::from("subscribe"),
-        args: args_ws,
-    };
-    msgs.push(subscribe_msg);
-    Ok(msgs)
+    adapter.build_subscribe(channel, inst_ids)
+}
+
+/// Subscribe acks received on a shard connection
+pub struct Subscription {
+    pending: HashSet<String>,
+    confirmed: usize,
+    started: Instant,
+    //Instruments rejected as unknown, to be dropped from the shard
+    pub invalid: Vec<String>,
+}
+
+impl Subscription {
+    pub fn new(inst_ids: &[String]) -> Self {
+        Self {
+            pending: inst_ids.iter().cloned().collect(),
+            confirmed: 0,
+            started: Instant::now(),
+            invalid: Vec::new(),
+        }
+    }
+
+    /// Fails when the exchange rejects a request or instruments are still unconfirmed after
+    /// `SUBSCRIBE_TIMEOUT`. Adapters that don't ack subscriptions are never timed out
+    pub fn check(&mut self, adapter: &dyn Adapter, res: &Value) -> Result<()> {
+        match adapter.parse_subscription(res) {
+            Some(SubscriptionEvent::Subscribed(inst_id)) => {
+                if self.pending.remove(&inst_id) {
+                    self.confirmed += 1;
+                    if self.pending.is_empty() {
+                        info!("Subscribed to {} instruments", self.confirmed);
+                    }
+                }
+            },
+            Some(SubscriptionEvent::Invalid(inst_id)) => {
+                warn!("Unknown instrument {}, dropping it", inst_id);
+                self.pending.remove(&inst_id);
+                self.invalid.push(inst_id);
+            },
+            Some(SubscriptionEvent::Failed(e)) => {
+                return Err(anyhow!("Subscription failed: {}", e))
+            },
+            None => {},
+        }
+        if self.confirmed > 0
+            && !self.pending.is_empty()
+            && self.started.elapsed() >= SUBSCRIBE_TIMEOUT
+        {
+            return Err(anyhow!(
+                "{} instruments weren't subscribed after {}s",
+                self.pending.len(),
+                SUBSCRIBE_TIMEOUT.as_secs()
+            ));
+        }
+        Ok(())
+    }
 }
 
-fn parse_symbols(mut pairs: Vec<String>) -> Vec<String> {
-    let mut index = 0;
-    pairs.retain(|x| {
-        let keep = x.contains("-USDT");
-        index += 1;
-        keep
-    });
-    pairs
+/// Unsubscribe + subscribe messages that make the exchange send a fresh book snapshot
+pub fn build_resubscribe(adapter: &dyn Adapter, inst_id: &str) -> Result<Vec<String>> {
+    let inst_ids = [inst_id.to_string()];
+    let mut msgs = adapter.build_unsubscribe(Channel::Books, &inst_ids)?;
+    msgs.extend(adapter.build_subscribe(Channel::Books, &inst_ids)?);
+    Ok(msgs)
 }
 
 pub async fn process_message(
-    exchange: &str,
+    adapter: &dyn Adapter,
     partition_count: &Mutex<HashMap<String, i32>>,
     client: Arc<Client>,
+    backfill: Option<&Backfill>,
     res: &Value,
 ) -> Result<()> {
     let msg_str =
@@ -114,61 +261,14 @@ pub async fn process_message(
     if msg_str.to_lowercase().contains("ping") || msg_str.to_lowercase().contains("pong") {
         info!("Got: {}", msg_str);
     };
-    let inst_id = res["arg"]["instId"].clone();
-    let inst_id_bytes = format!("{}", inst_id).replace('\"', "").as_bytes().to_vec();
-    let chan = Channel::from_str(res["arg"]["channel"].as_str().unwrap_or_default());
-
-    if let Ok(channel) = chan {
-        if res["data"] != json!(null) {
-            match channel {
-                Channel::Tickers => {
-                    send_message(
-                        exchange,
-                        channel,
-                        res,
-                        partition_count,
-                        client,
-                        inst_id_bytes,
-                    )
-                    .await
-                },
-                Channel::Trades => {
-                    send_message(
-                        exchange,
-                        channel,
-                        res,
-                        partition_count,
-                        client,
-                        inst_id_bytes,
-                    )
-                    .await
-                },
-                Channel::Books => {
-                    send_message(
-                        exchange,
-                        channel,
-                        res,
-                        partition_count,
-                        client,
-                        inst_id_bytes,
-                    )
-                    .await
-                },
-                Channel::Candle1m => {
-                    send_message(
-                        exchange,
-                        channel,
-                        res,
-                        partition_count,
-                        client,
-                        inst_id_bytes,
-                    )
-                    .await
-                },
-            }?;
-        };
+
+    if let Some(msg) = adapter.normalize(res)? {
+        if let Some(backfill) = backfill {
+            backfill.check(&msg).await?;
+        }
+        send_message(adapter.name(), msg, partition_count, client, false).await?;
     } else {
-        info!("Nothing to do with channel: {chan:?}");
+        debug!("Nothing to do with message: {}", msg_str);
     }
     Ok(())
 }
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use exchange_observer::Alerts;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertRule {
+    CandleChange,
+    VolumeSpike,
+    Change24h,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub instid: String,
+    pub rule: AlertRule,
+    //% change, or times the average volume for volume spikes
+    pub value: f64,
+    pub price: f64,
+    pub vol: f64,
+}
+
+impl ToString for Alert {
+    fn to_string(&self) -> String {
+        let detail = match self.rule {
+            AlertRule::CandleChange => format!("1m change: %{:.2}", self.value),
+            AlertRule::VolumeSpike => format!("1m volume: {:.1}x average", self.value),
+            AlertRule::Change24h => format!("24h change: %{:.2}", self.value),
+        };
+        format!(
+            "{} | {} | Price: {} | Vol: {:.0}",
+            self.instid, detail, self.price, self.vol
+        )
+    }
+}
+
+/// Checks every fetched token against the `[alerts]` rules, at most once per
+/// token and rule every `cooldown` seconds
+#[derive(Debug, Clone, Default)]
+pub struct AlertEngine {
+    pub cfg: Alerts,
+    sent: HashMap<(String, AlertRule), DateTime<Utc>>,
+}
+
+impl AlertEngine {
+    pub fn new(cfg: Option<Alerts>) -> Self {
+        Self {
+            cfg: cfg.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn check(&mut self, tokens: &[Token], now: DateTime<Utc>) -> Vec<Alert> {
+        if !self.cfg.enable {
+            return Vec::new();
+        }
+        let mut alerts = Vec::new();
+        for t in tokens {
+            if t.vol < self.cfg.min_vol.unwrap_or_default() {
+                continue;
+            }
+            for (rule, value) in self.triggered(t) {
+                let key = (t.instid.clone(), rule);
+                let cooling_down = self
+                    .sent
+                    .get(&key)
+                    .map_or(false, |ts| (now - *ts).num_seconds() < self.cfg.cooldown);
+                if cooling_down {
+                    continue;
+                }
+                self.sent.insert(key, now);
+                alerts.push(Alert {
+                    instid: t.instid.clone(),
+                    rule,
+                    value,
+                    price: t.price,
+                    vol: t.vol,
+                });
+            }
+        }
+        alerts
+    }
+
+    fn triggered(&self, t: &Token) -> Vec<(AlertRule, f64)> {
+        let mut rules = Vec::new();
+        let Some(last) = t.candlesticks.last() else {
+            return rules;
+        };
+
+        if let Some(threshold) = self.cfg.candle_change {
+            if last.change.abs() >= threshold {
+                rules.push((AlertRule::CandleChange, last.change as f64));
+            }
+        }
+
+        if let Some(times) = self.cfg.volume_spike {
+            let previous = &t.candlesticks[..t.candlesticks.len() - 1];
+            if !previous.is_empty() {
+                let average = previous.iter().map(|c| c.vol).sum::<f64>() / previous.len() as f64;
+                if average > 0.0 && last.vol >= average * times {
+                    rules.push((AlertRule::VolumeSpike, last.vol / average));
+                }
+            }
+        }
+
+        //Only set for tokens that made it to the selection (get_tickers)
+        if let Some(threshold) = self.cfg.change24h {
+            if t.change24h != 0.0 && t.change24h.abs() >= threshold {
+                rules.push((AlertRule::Change24h, t.change24h as f64));
+            }
+        }
+        rules
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use console::Term;
 use futures::stream::{self, StreamExt, TryStreamExt};
@@ -7,7 +7,7 @@ use pushover_rs::{
 };
 use time::Instant;
 
-use crate::prelude::*;
+use crate::{exchange, prelude::*, strategies};
 
 #[derive(Debug)]
 pub struct App {
@@ -20,6 +20,16 @@ pub struct App {
     pub term: Term,
     pub pushover: Pushover,
     pub exchange: Exchange,
+    pub broker: Arc<dyn Broker>,
+    pub simulator: FillSimulator,
+    pub instruments: Instruments,
+    pub risk: RiskManager,
+    pub sizing: PositionSizer,
+    pub regime: RegimeDetector,
+    pub events: Publisher,
+    pub alerts: AlertEngine,
+    //Strategies by hash, portfolio tokens keep trading with the one they were bought with
+    pub strategies: HashMap<String, Strategy>,
     pub deny_list: Vec<String>,
     pub db_session: Arc<Session>,
 }
@@ -31,6 +41,8 @@ pub struct Time {
     pub now: Instant,
     pub elapsed: Duration,
     pub uptime: Duration,
+    //Clock is driven by a backtest instead of the system time
+    pub simulated: bool,
 }
 impl Default for Time {
     fn default() -> Self {
@@ -40,6 +52,7 @@ impl Default for Time {
             now: Instant::now(),
             elapsed: Duration::milliseconds(0),
             uptime: Duration::seconds(0),
+            simulated: false,
         }
     }
 }
@@ -53,6 +66,17 @@ impl App {
             .await?;
         session.use_keyspace(&cfg.database.keyspace, false).await?;
         let session = Arc::new(session);
+        let exchange = cfg.exchange.clone().unwrap_or_default();
+        let mut broker = exchange::from_name(&exchange.name)?;
+        //Order and balance updates from the private websocket, REST is kept as fallback
+        if exchange.enable_trading && broker.name() == "okx" {
+            broker = Arc::new(exchange::okx_ws::Streamed::new(
+                broker,
+                &exchange.authentication,
+            ));
+        }
+
+        let instruments = Instruments::load(&session, broker.as_ref()).await?;
 
         Ok(App {
             round_id: 0,
@@ -62,12 +86,157 @@ impl App {
             logs: Vec::new(),
             tokens: Vec::new(),
             deny_list: cfg.strategy.deny_list.clone().unwrap_or_default(),
-            exchange: cfg.exchange.clone().unwrap_or_default(),
+            broker,
+            simulator: FillSimulator::new(&exchange),
+            instruments,
+            risk: RiskManager::new(cfg.risk.clone()),
+            sizing: PositionSizer::new(cfg.sizing.clone(), cfg.account.spendable)?,
+            regime: RegimeDetector::new(cfg.regime.clone()),
+            events: Publisher::new(cfg).await?,
+            alerts: AlertEngine::new(cfg.alerts.clone()),
+            strategies: HashMap::new(),
+            exchange,
             term: Term::stdout(),
             pushover: cfg.pushover.clone().unwrap_or_default(),
             db_session: session,
         })
     }
+    /// Run a full trading round: select tokens, buy, update the portfolio and sell.
+    /// Shared between the live loop and backtests.
+    pub async fn cycle(
+        &mut self,
+        mut account: Account,
+        strategy: &Strategy,
+    ) -> Result<Account, Box<dyn Error>> {
+        //Retrieve and process top tokens
+        self.fetch_tokens(strategy.timeframe).await?;
+        self.tokens = self
+            .update_candles(strategy.timeframe, self.tokens.clone())
+            .await?;
+
+        //Alerts look at every fetched token, before the strategy filters
+        let alerts = self.alerts.check(&self.tokens, self.time.utc);
+        self.send_alerts(alerts).await?;
+
+        if let Some(regime) = self.regime.update(&self.db_session, self.time.utc).await? {
+            self.logs
+                .push(format!("Market regime is now {}", regime.to_string()));
+        }
+
+        self.filter_invalid(strategy, account.balance.spendable)?;
+        self.clean_top(strategy.top)
+            .get_tickers()
+            .await?
+            .get_books()
+            .await?;
+        self.events
+            .select(&self.tokens, &strategy.hash, self.time.utc);
+
+        //update timers in portfolio tokens
+        if !self.risk.is_halted() && !self.regime.pauses(strategy) {
+            account = self.buy_tokens(account, strategy).await?;
+        }
+
+        //update portfolio and tracked tokens
+        self.update_cooldowns(&account.portfolio);
+
+        account.portfolio = self.update_timeouts(account.portfolio, strategy);
+        account.portfolio = self
+            .update_candles(strategy.timeframe, account.portfolio)
+            .await?;
+
+        //update portfolio
+        for token in account.portfolio.iter_mut() {
+            let token_strategy = self
+                .regime
+                .adjust(self.strategies.get(&token.strategy).unwrap_or(strategy));
+            let quote = if self.exchange.enable_trading {
+                None
+            } else {
+                self.get_quote(&token.instid).await?
+            };
+            let instrument = self.instruments.get(&token.instid);
+            token
+                .update_reports(token_strategy.timeout)
+                .update_orders(
+                    self.broker.as_ref(),
+                    self.exchange.enable_trading,
+                    &self.exchange.authentication,
+                    &self.simulator,
+                    quote.as_ref(),
+                    instrument,
+                )
+                .await?
+                .expire_orders(self.broker.as_ref(), &self.exchange, self.time.utc)
+                .await?
+                .tag_invalid(&self.tokens, &token_strategy)?;
+        }
+
+        account.balance.set_current(0.0);
+        account.calculate_balance(self).await?.calculate_earnings();
+
+        if let Some(reason) = self.risk.check(&account, self.time.utc) {
+            self.halt(&account, reason, strategy).await?;
+        }
+        if self.risk.is_halted() {
+            account.liquidate();
+        }
+
+        account = self.sync_exits(account, strategy).await?;
+        //account = app.tag_invalid_tokens(account, &cfg.strategy)?;
+        account = self.sell_tokens(account, strategy).await?;
+
+        if let Err(e) = self.events.flush().await {
+            self.logs.push(format!("Unable to publish events: {}", e));
+        }
+        Ok(account)
+    }
+
+    /// Stop trading: notify and persist the halt so it has to be acknowledged before restarting.
+    /// Backtests only stop buying.
+    pub async fn halt(
+        &mut self,
+        account: &Account,
+        reason: HaltReason,
+        strategy: &Strategy,
+    ) -> Result<()> {
+        let halt = Halt {
+            exchange: self.exchange.name.clone(),
+            reason: reason.to_string(),
+            balance: account.balance.current,
+            strategy: strategy.hash.clone(),
+            acknowledged: false,
+            ts: self.time.utc.timestamp_millis().to_string(),
+        };
+        log::warn!("{}", halt.to_string());
+        self.logs.push(halt.to_string());
+        if self.time.simulated {
+            return Ok(());
+        }
+        halt.save(&self.db_session).await?;
+        if self.pushover.enable {
+            self.notify(
+                "Trading Halted".to_string(),
+                format!(
+                    "{}\nBalance: {:.2} | Earned: {:.2}\nSelling portfolio",
+                    halt.reason, account.balance.current, account.earnings
+                ),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Upper bound (unix ms) for time based queries.
+    /// Live runs read up to the latest row, backtests stop at the simulated clock.
+    pub fn until(&self) -> i64 {
+        if self.time.simulated {
+            self.time.utc.timestamp_millis()
+        } else {
+            i64::MAX
+        }
+    }
+
     pub async fn send_notifications(&self, account: &Account) -> Result<()> {
         for t in account.portfolio.iter() {
             //send notifications
@@ -99,6 +268,16 @@ impl App {
         }
         Ok(())
     }
+    pub async fn send_alerts(&mut self, alerts: Vec<Alert>) -> Result<()> {
+        for alert in alerts {
+            let msg = alert.to_string();
+            self.logs.push(format!("Alert: {}", msg));
+            if self.pushover.enable && !self.time.simulated {
+                self.notify(format!("{} Alert", alert.instid), msg).await?;
+            }
+        }
+        Ok(())
+    }
     pub async fn notify(&self, title: String, msg: String) -> Result<PushoverResponse> {
         let now = self.time.utc.timestamp();
         let message: Message = MessageBuilder::new(&self.pushover.key, &self.pushover.token, &msg)
@@ -113,10 +292,12 @@ impl App {
     }
 
     pub async fn get_tickers(&mut self) -> Result<&mut Self> {
+        let until = self.until();
         for t in self.tokens.iter_mut() {
             let query = format!(
-                "select last,sodutc0,volccy24h, high24h, low24h from tickers WHERE instid='{}' limit 1;",
+                "select last,sodutc0,volccy24h, high24h, low24h from tickers WHERE instid='{}' and ts <= {} limit 1;",
                 t.instid,
+                until,
             );
 
             if let Some(rows) = self.db_session.query(&*query, &[]).await?.rows {
@@ -131,13 +312,60 @@ impl App {
         }
         Ok(self)
     }
+    /// Spread and depth from the latest order book snapshot (requires the books channel)
+    pub async fn get_books(&mut self) -> Result<&mut Self> {
+        for i in 0..self.tokens.len() {
+            if let Some((asks, bids)) = self.get_book(&self.tokens[i].instid).await? {
+                self.tokens[i].book = BookDepth::new(&asks, &bids).unwrap_or_default();
+            }
+        }
+        Ok(self)
+    }
+    pub async fn get_book(
+        &self,
+        instid: &str,
+    ) -> Result<Option<(Vec<OrderEntry>, Vec<OrderEntry>)>> {
+        let query = format!(
+            "select asks, bids from books WHERE instid='{}' and ts <= {} limit 1;",
+            instid,
+            self.until(),
+        );
+        if let Some(rows) = self.db_session.query(&*query, &[]).await?.rows {
+            if let Some(row) = rows
+                .into_typed::<(Vec<OrderEntry>, Vec<OrderEntry>)>()
+                .next()
+            {
+                return Ok(Some(row?));
+            }
+        };
+        Ok(None)
+    }
+    /// Latest best bid/ask (and book levels when stored) used to fill paper orders
+    pub async fn get_quote(&self, instid: &str) -> Result<Option<Quote>> {
+        let query = format!(
+            "select askpx, asksz, bidpx, bidsz from tickers WHERE instid='{}' and ts <= {} limit 1;",
+            instid,
+            self.until(),
+        );
+        let mut ticker = None;
+        if let Some(rows) = self.db_session.query(&*query, &[]).await?.rows {
+            if let Some(row) = rows.into_typed::<(f64, f64, f64, f64)>().next() {
+                ticker = Some(row?);
+            }
+        };
+        match ticker {
+            Some(ticker) => Ok(Some(Quote::new(ticker, self.get_book(instid).await?))),
+            None => Ok(None),
+        }
+    }
     pub fn update_timeouts(&mut self, mut tokens: Vec<Token>, strategy: &Strategy) -> Vec<Token> {
         self.tokens.iter().for_each(|s| {
             if let Some(token) = tokens.iter_mut().find(|t| t.instid == s.instid) {
+                let strategy = self.strategies.get(&token.strategy).unwrap_or(strategy);
                 if token
                     .candlesticks
                     .last()
-                    .unwrap_or(&Candlestick::new(token.price))
+                    .unwrap_or(&Candlestick::new(token.price, self.time.utc))
                     .change
                     > strategy.min_change
                 {
@@ -152,6 +380,7 @@ impl App {
             };
 
             if t.change == 0.0 && t.timeout.num_seconds() <= 0 {
+                let strategy = self.strategies.get(&t.strategy).unwrap_or(strategy);
                 t.timeout = Duration::seconds(strategy.timeout)
             };
         }
@@ -182,6 +411,8 @@ impl App {
             .unwrap()
             .with_nanosecond(0)
             .unwrap();
+        let until = self.until();
+        let current_minute = Duration::milliseconds(dt.timestamp_millis());
 
         //last -timeframe- candles
         let get_candles_query = self
@@ -193,19 +424,29 @@ impl App {
         let get_tickers_query = self
             .db_session
             .prepare(
-                "SELECT last, lastsz, ts FROM tickers WHERE instid=? AND ts >= ? order by ts asc",
+                "SELECT last, lastsz, ts FROM tickers WHERE instid=? AND ts >= ? AND ts <= ? order by ts asc",
+            )
+            .await?;
+
+        //Last min trades, used instead of tickers with exchange.trade_candles
+        let trade_candles = self.exchange.trade_candles.unwrap_or(false);
+        let get_trades_query = self
+            .db_session
+            .prepare(
+                "SELECT px, sz, side, ts FROM trades WHERE instid=? AND ts >= ? AND ts <= ? order by ts asc",
             )
             .await?;
 
         //Current price
         let get_price_query = self
             .db_session
-            .prepare("SELECT last FROM tickers WHERE instid=? LIMIT 1")
+            .prepare("SELECT last FROM tickers WHERE instid=? AND ts <= ? LIMIT 1")
             .await?;
 
         stream::iter(tokens.into_iter().map(|mut token| {
             let get_candle_stmt = get_candles_query.clone();
             let get_ticker_stmt = get_tickers_query.clone();
+            let get_trade_stmt = get_trades_query.clone();
             let get_price_stmt = get_price_query.clone();
             async move {
                 //Get all candles in the selected timeframe
@@ -219,7 +460,7 @@ impl App {
                     .rows
                 {
                     for row in rows.into_typed::<Candlestick>() {
-                        let candle = row.unwrap_or(Candlestick::new(token.price));
+                        let candle = row.unwrap_or(Candlestick::new(token.price, dt));
                         token.add_or_update_candle(candle)
                     }
                 };
@@ -235,7 +476,7 @@ impl App {
                 //Token price
                 token.price = if let Some(rows) = self
                     .db_session
-                    .execute(&get_price_stmt, (&token.instid,))
+                    .execute(&get_price_stmt, (&token.instid, until))
                     .await?
                     .rows
                 {
@@ -250,12 +491,33 @@ impl App {
                     token.price
                 };
 
-                //Last candle built from last minute of tickers
-                if let Some(rows) = self
+                //Last candle built from last minute of trades (with the aggressor flow) or tickers
+                let last_candle = if trade_candles {
+                    let trades: Vec<(f64, f64, String, Duration)> = match self
+                        .db_session
+                        .execute(
+                            &get_trade_stmt,
+                            (&token.instid, last_min.timestamp_millis(), until),
+                        )
+                        .await?
+                        .rows
+                    {
+                        Some(rows) => rows
+                            .into_typed::<(f64, f64, String, Duration)>()
+                            .filter_map(Result::ok)
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    token.set_imbalance(&trades);
+                    Some(
+                        Candlestick::from_trades(&token.instid, &trades, dt)
+                            .unwrap_or_else(|| Candlestick::new(0.0, dt)),
+                    )
+                } else if let Some(rows) = self
                     .db_session
                     .execute(
                         &get_ticker_stmt,
-                        (&token.instid, last_min.timestamp_millis()),
+                        (&token.instid, last_min.timestamp_millis(), until),
                     )
                     .await?
                     .rows
@@ -264,14 +526,21 @@ impl App {
                         .into_typed::<(f64, f64, Duration)>()
                         .filter_map(Result::ok)
                         .collect();
+                    Some(
+                        Candlestick::from_tickers(&token.instid, &tickers, dt)
+                            .unwrap_or_else(|| Candlestick::new(0.0, dt)),
+                    )
+                } else {
+                    None
+                };
 
+                if let Some(mut last_candle) = last_candle {
                     token.candlesticks.sort_by(|a, b| {
                         a.ts.partial_cmp(&b.ts)
                             .expect("unable to compare timestamps")
                     });
-                    let mut last_candle =
-                        Candlestick::from_tickers(&token.instid, &tickers).unwrap_or_default();
                     if last_candle.change == 0.0 {
+                        last_candle.ts = current_minute;
                         last_candle.open = token.price;
                         last_candle.high = token.price;
                         last_candle.low = token.price;
@@ -284,11 +553,12 @@ impl App {
                     token.candlesticks.remove(0);
                 }
                 token.change = 0.0;
-                token.sum_candles();
+                //Indicators need the candles in order
                 token.candlesticks.sort_by(|a, b| {
                     a.ts.partial_cmp(&b.ts)
                         .expect("unable to compare timestamps")
                 });
+                token.sum_candles();
                 Ok(token)
             }
         }))
@@ -302,12 +572,39 @@ impl App {
         mut account: Account,
         strategy: &Strategy,
     ) -> Result<Account> {
+        let behavior = strategies::from_strategy(strategy)?;
+        //Portfolio size lowered while risk-off
+        let limits = self.regime.adjust(strategy);
         //Add to portfolio first
         for token in self.tokens.iter_mut() {
-            if token.cooldown <= Duration::milliseconds(0)
-                && !account.portfolio.iter().any(|p| token.instid == p.instid)
+            if token.cooldown > Duration::milliseconds(0)
+                || account.portfolio.iter().any(|p| token.instid == p.instid)
             {
-                account.add_token(token, strategy);
+                continue;
+            }
+            //Don't stack positions that move together
+            if let (Some(max), Some(correlation)) = (
+                strategy.max_correlation,
+                token.max_correlation(&account.portfolio),
+            ) {
+                if correlation > max {
+                    continue;
+                }
+            }
+            let size = self
+                .sizing
+                .size(token, &account.balance, &self.db_session, self.time.utc)
+                .await?;
+            let size = behavior.size(token, size);
+            if size > 0.0 && self.risk.can_open(&account, size) {
+                if account.add_token(token, &limits, size) {
+                    self.logs.push(format!(
+                        "[{}] Position size: {:.2} USDT ({} sizing)",
+                        token.instid,
+                        size,
+                        self.sizing.mode.to_string()
+                    ));
+                }
                 token.cooldown = self.cooldown;
             }
         }
@@ -320,30 +617,44 @@ impl App {
                 .iter()
                 .any(|o| o.side == Side::Buy && o.state != OrderState::Cancelled);
 
-            if !buy_orders {
-                t.balance.start = account.balance.spendable / t.price;
+            //Cancelled buys that got partially filled leave the token trading
+            if !buy_orders && matches!(t.status, token::Status::Buying | token::Status::Waiting) {
+                t.strategy = strategy.hash.clone();
+                self.strategies
+                    .entry(strategy.hash.clone())
+                    .or_insert_with(|| strategy.clone());
+                t.balance.start = t.allocated(account.balance.spendable) / t.price;
                 t.configure_from_report(strategy, &self.db_session).await;
 
                 {
-                    let order = t
-                        .buy(
-                            self.exchange.enable_trading,
-                            account.authentication.clone(),
-                            strategy,
-                        )
-                        .await?
-                        .orders
-                        .as_ref()
-                        .and_then(|orders| orders.last())
-                        .unwrap();
+                    t.buy(
+                        self.broker.as_ref(),
+                        self.exchange.enable_trading,
+                        account.authentication.clone(),
+                        strategy,
+                        self.instruments.get(&t.instid),
+                    )
+                    .await?;
+                    if self.time.simulated {
+                        t.stamp_last_order(self.time.utc);
+                    }
+                    let order = t.orders.as_ref().and_then(|orders| orders.last()).unwrap();
 
                     order.save(&self.db_session).await?;
+                    self.events.push(
+                        Event::OrderPlaced {
+                            order: order.clone(),
+                            state: order.state.to_string(),
+                        },
+                        &strategy.hash,
+                        self.time.utc,
+                    );
                     let log_line = self.build_order_log(order);
                     self.logs.push(log_line);
                     self.round_id += 1;
                 }
 
-                t.report = Report::new(self.round_id, &strategy.hash, t);
+                t.report = Report::new(self.round_id, &strategy.hash, t, self.time.utc);
             }
         }
         Ok(account)
@@ -357,7 +668,7 @@ impl App {
         for t in account.portfolio.iter_mut() {
             let found = self.tokens.iter().any(|s| t.instid == s.instid);
             if t.status == token::Status::Trading {
-                t.exit_reason = t.get_exit_reason(strategy, found);
+                t.exit_reason = strategies::from_strategy(strategy)?.exit(t, strategy, found);
             }
             if t.exit_reason.is_some() {
                 t.status = token::Status::Selling;
@@ -378,7 +689,7 @@ impl App {
             size = order.sz,
             response = if self.exchange.enable_trading {
                 match order.clone().response {
-                    Some(r) => r.data[0].clone().s_msg,
+                    Some(r) => r.msg,
                     None => format!("{:?}", order.response),
                 }
             } else {
@@ -402,20 +713,21 @@ impl App {
                 .unwrap_or_default()
                 .iter()
                 .filter_map(|o| {
-                    if o.side == Side::Sell && o.state == OrderState::Filled {
-                        o.sz.parse::<f64>().ok()
-                    } else {
-                        None
+                    if o.side != Side::Sell {
+                        return None;
+                    }
+                    match o.state {
+                        OrderState::Filled => o.filled().map(|(_, size)| size),
+                        OrderState::Cancelled if o.fill_sz > 0.0 => Some(o.fill_sz),
+                        _ => None,
                     }
                 })
                 .sum();
 
-            let live_orders = t
-                .orders
-                .clone()
-                .unwrap_or_default()
-                .iter()
-                .any(|o| o.side == Side::Sell && o.state == OrderState::Live);
+            let live_orders = t.orders.clone().unwrap_or_default().iter().any(|o| {
+                o.side == Side::Sell
+                    && matches!(o.state, OrderState::Live | OrderState::PartiallyFilled)
+            });
 
             let balance_threshold = t.balance.start * 0.99;
             if t.status == token::Status::Selling
@@ -423,67 +735,199 @@ impl App {
                 && !live_orders
                 && t.exit_reason.is_some()
             {
+                let strategy = self
+                    .strategies
+                    .get(&t.strategy)
+                    .cloned()
+                    .unwrap_or_else(|| strategy.clone());
                 {
-                    let order = t
-                        .sell(
-                            self.exchange.enable_trading,
-                            account.authentication.clone(),
-                            strategy,
-                        )
-                        .await?
-                        .orders
-                        .as_ref()
-                        .and_then(|orders| orders.last())
-                        .unwrap();
+                    t.sell(
+                        self.broker.as_ref(),
+                        self.exchange.enable_trading,
+                        account.authentication.clone(),
+                        &strategy,
+                        self.instruments.get(&t.instid),
+                    )
+                    .await?;
+                    if self.time.simulated {
+                        t.stamp_last_order(self.time.utc);
+                    }
+                    let order = t.orders.as_ref().and_then(|orders| orders.last()).unwrap();
 
                     order.save(&self.db_session).await?;
+                    self.events.push(
+                        Event::OrderPlaced {
+                            order: order.clone(),
+                            state: order.state.to_string(),
+                        },
+                        &strategy.hash,
+                        self.time.utc,
+                    );
                     let log_line = self.build_order_log(order);
                     self.logs.push(log_line);
                 }
+                self.close_position(t, &strategy).await?;
+            }
+        }
+        Ok(account)
+    }
 
-                //build up deny list if stoploss.
-                let denied = self
-                    .deny_list
-                    .iter()
-                    .any(|i| format!("{}-USDT", i) == t.instid);
+    /// Report a position once its sell order is placed
+    pub async fn close_position(&mut self, t: &mut Token, strategy: &Strategy) -> Result<()> {
+        //build up deny list if stoploss.
+        let denied = self
+            .deny_list
+            .iter()
+            .any(|i| format!("{}-USDT", i) == t.instid);
+
+        //deny tokens to be bought again
+        if t.exit_reason == Some(ExitReason::Stoploss) && strategy.avoid_after_stoploss && !denied {
+            self.deny_list.push(t.instid.replace("-USDT", ""))
+        };
 
-                //deny tokens to be bought again
-                if t.exit_reason == Some(ExitReason::Stoploss)
-                    && strategy.avoid_after_stoploss
-                    && !denied
-                {
-                    self.deny_list.push(t.instid.replace("-USDT", ""))
-                };
+        // Create token report
+        let usdt_balance = t.balance.current * t.price;
+        let usdt_fee = calculate_fees(usdt_balance, self.exchange.taker_fee);
+        let usdt_balance_after_fees = usdt_balance - usdt_fee;
+        let earnings = (t.balance.start * t.buy_price) - usdt_balance_after_fees;
+        let earnings = if earnings < 0.0 {
+            usdt_balance_after_fees - (t.balance.start * t.buy_price)
+        } else {
+            -earnings
+        };
+        t.report.earnings = earnings;
+        t.report.change = t.change;
+        self.risk
+            .record_exit(t.report.round_id, t.exit_reason.as_ref());
+
+        t.report.save(&self.db_session).await?;
+        self.events.push(
+            Event::PositionClosed {
+                instid: t.instid.clone(),
+                round_id: t.report.round_id,
+                reason: t.exit_reason.clone(),
+                buy_price: t.buy_price,
+                sell_price: t.price,
+                change: t.change,
+                earnings: t.report.earnings,
+            },
+            &strategy.hash,
+            self.time.utc,
+        );
+        Ok(())
+    }
 
-                // Create token report
-                let usdt_balance = t.balance.current * t.price;
-                let usdt_fee = calculate_fees(usdt_balance, self.exchange.taker_fee);
-                let usdt_balance_after_fees = usdt_balance - usdt_fee;
-                let earnings = (t.balance.start * t.buy_price) - usdt_balance_after_fees;
-                let earnings = if earnings < 0.0 {
-                    usdt_balance_after_fees - (t.balance.start * t.buy_price)
+    /// Keep the take-profit/stop-loss of every position on the exchange (`native_exits`):
+    /// place them once bought, cancel them when the scheduler sells on its own and take over
+    /// the sell order when they trigger.
+    pub async fn sync_exits(
+        &mut self,
+        mut account: Account,
+        strategy: &Strategy,
+    ) -> Result<Account> {
+        if !self.exchange.enable_trading || !self.exchange.native_exits.unwrap_or(false) {
+            return Ok(account);
+        }
+        let auth = self.exchange.authentication.clone();
+        for t in account.portfolio.iter_mut() {
+            let strategy = self
+                .strategies
+                .get(&t.strategy)
+                .cloned()
+                .unwrap_or_else(|| strategy.clone());
+
+            if let Some(id) = t.exit_id.clone() {
+                let state = if t.status == token::Status::Selling
+                    && self.broker.cancel_exit(&t.instid, &id, &auth).await?
+                {
+                    ExitState::Cancelled
                 } else {
-                    -earnings
+                    self.broker.exit_state(&t.instid, &id, &auth).await?
                 };
-                t.report.earnings = earnings;
-                t.report.change = t.change;
+                match state {
+                    ExitState::Pending => continue,
+                    ExitState::Cancelled => {
+                        self.logs
+                            .push(format!("[{}] Exit orders {} cancelled", t.instid, id));
+                        t.exit_id = None;
+                    },
+                    ExitState::Triggered {
+                        ord_id,
+                        take_profit,
+                    } => {
+                        t.exit_id = None;
+                        let reason = if take_profit {
+                            ExitReason::Cashout
+                        } else {
+                            ExitReason::Stoploss
+                        };
+                        let mut order = Order::new(
+                            &t.instid,
+                            t.price.to_string(),
+                            t.balance.available.to_string(),
+                            Side::Sell,
+                            "market",
+                            &strategy.hash,
+                        );
+                        order.id = ord_id;
+                        order.save(&self.db_session).await?;
+                        self.events.push(
+                            Event::OrderPlaced {
+                                order: order.clone(),
+                                state: order.state.to_string(),
+                            },
+                            &strategy.hash,
+                            self.time.utc,
+                        );
+                        self.logs.push(format!(
+                            "[{}] Exit orders triggered ({}), selling with order {}",
+                            t.instid,
+                            reason.to_string(),
+                            order.id
+                        ));
+                        t.orders.get_or_insert_with(Vec::new).push(order);
+                        t.report.reason = reason.to_string();
+                        t.exit_reason = Some(reason);
+                        t.status = token::Status::Selling;
+                        self.close_position(t, &strategy).await?;
+                        continue;
+                    },
+                }
+            }
 
-                t.report.save(&self.db_session).await?;
+            if t.status != token::Status::Trading || t.balance.available * t.price <= 2.0 {
+                continue;
+            }
+            let instrument = self.instruments.get(&t.instid);
+            let size =
+                instrument.map_or(t.balance.available, |i| i.round_size(t.balance.available));
+            let format_px =
+                |px: f64| instrument.map_or_else(|| px.to_string(), |i| i.round_price(px));
+            let tp_px = format_px(t.buy_price * (1.0 + strategy.cashout as f64 / 100.0));
+            let sl_px = format_px(t.buy_price * (1.0 - strategy.stoploss as f64 / 100.0));
+            let sz = instrument.map_or_else(|| size.to_string(), |i| i.format_size(size));
+            t.exit_id = self
+                .broker
+                .place_exit(&t.instid, &sz, &tp_px, &sl_px, &auth)
+                .await?;
+            if let Some(id) = &t.exit_id {
+                self.logs.push(format!(
+                    "[{}] Exit orders {} placed. Take profit: {} Stop loss: {}",
+                    t.instid, id, tp_px, sl_px
+                ));
             }
         }
         Ok(account)
     }
 
-    pub fn filter_invalid(&mut self, strategy: &Strategy, spendable: f64) -> &mut Self {
+    /// Keep the tokens selected by the strategy kind, best ranked first
+    pub fn filter_invalid(&mut self, strategy: &Strategy, spendable: f64) -> Result<&mut Self> {
+        let behavior = strategies::from_strategy(strategy)?;
         let deny_list = self.deny_list.clone();
         self.tokens
-            .retain(|t| t.is_valid(&deny_list, strategy, spendable));
-        self.tokens.sort_by(|b, a| {
-            b.std_deviation
-                .partial_cmp(&a.change)
-                .expect("unable to compare change")
-        });
-        self
+            .retain(|t| behavior.select(t, strategy, &deny_list, spendable));
+        self.tokens.sort_by(|a, b| behavior.rank(a, b));
+        Ok(self)
     }
 
     pub fn update_cooldowns(&mut self, portfolio: &[Token]) -> &mut Self {
@@ -509,8 +953,9 @@ impl App {
         let dt = xdt.with_second(0).unwrap().with_nanosecond(0).unwrap();
 
         let query = format!(
-            "SELECT * FROM okx.candle1m WHERE ts >= '{}'",
-            dt.timestamp_millis()
+            "SELECT * FROM candle1m WHERE ts >= '{}' AND ts <= '{}'",
+            dt.timestamp_millis(),
+            self.until()
         );
 
         if let Some(rows) = self.db_session.query(&*query, &[]).await?.rows {
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+//Simulated seconds between each trading round
+const DEFAULT_STEP_SECS: i64 = 5;
+//Log progress every (x) simulated minutes
+const PROGRESS_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct Backtest {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub step: Duration,
+    pub strategy_path: Option<String>,
+    //Replay strategies that lost money before
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Summary {
+    pub strategy: String,
+    pub from_ts: String,
+    pub to_ts: String,
+    pub rounds: u64,
+    pub trades: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub win_rate: f32,
+    pub balance: f64,
+    pub earnings: f64,
+    pub fee_spend: f64,
+    pub max_drawdown: f32,
+    pub open_positions: u64,
+    pub ts: String,
+}
+
+impl Backtest {
+    /// Parse `backtest --from <ts> --to <ts> [--strategy <file>] [--step <secs>] [--force]`.
+    /// Timestamps can be RFC3339 (2023-06-01T10:00:00Z) or unix seconds.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut from = None;
+        let mut to = None;
+        let mut step = Duration::seconds(DEFAULT_STEP_SECS);
+        let mut strategy_path = None;
+        let mut force = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--force" {
+                force = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for argument {}", arg))?;
+            match arg.as_str() {
+                "--from" => from = Some(parse_timestamp(value)?),
+                "--to" => to = Some(parse_timestamp(value)?),
+                "--strategy" => strategy_path = Some(value.to_string()),
+                "--step" => step = Duration::seconds(value.parse::<i64>()?),
+                _ => return Err(anyhow::anyhow!("Unknown argument: {}", arg)),
+            }
+        }
+
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) if from < to => (from, to),
+            (Some(_), Some(_)) => return Err(anyhow::anyhow!("--from should be before --to")),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Usage: scheduler backtest --from <ts> --to <ts> [--strategy <config.toml>] [--step <secs>] [--force]"
+                ))
+            },
+        };
+        if step.num_seconds() <= 0 {
+            return Err(anyhow::anyhow!("--step should be at least 1 second"));
+        }
+
+        Ok(Self {
+            from,
+            to,
+            step,
+            strategy_path,
+            force,
+        })
+    }
+
+    pub async fn run(&self, mut cfg: AppConfig) -> Result<Summary, Box<dyn Error>> {
+        if let Some(path) = &self.strategy_path {
+            cfg.strategy = Strategy::load(path)?;
+        }
+        cfg.strategy.validate()?;
+        cfg.strategy.hash = cfg.strategy.get_hash();
+        //Replayed decisions aren't published
+        cfg.events = None;
+
+        let mut app = App::init(&cfg).await?;
+        History::check(&app.db_session, &cfg.strategy, self.force).await?;
+        //Never send orders to the exchange while replaying history
+        app.exchange.enable_trading = false;
+        app.time.simulated = true;
+        app.time.started = self.from;
+        app.time.utc = self.from;
+        app.time.elapsed = self.step;
+        app.set_cooldown(cfg.strategy.cooldown);
+        app.save_strategy(&cfg.strategy).await?;
+
+        let mut account = Account::new().set_balance(cfg.account.balance, cfg.account.spendable);
+
+        log::info!(
+            "Starting backtest of strategy {} from {} to {}",
+            cfg.strategy.hash,
+            self.from,
+            self.to
+        );
+
+        //Closed rounds, indexed by round_id
+        let mut reports: HashMap<u64, Report> = HashMap::new();
+        let mut peak = account.balance.start;
+        let mut max_drawdown: f32 = 0.0;
+
+        while app.time.utc <= self.to {
+            account = app.cycle(account, &cfg.strategy).await?;
+
+            for t in account.portfolio.iter() {
+                if t.status == Status::Exited {
+                    reports.insert(t.report.round_id, t.report.clone());
+                }
+            }
+            account.clean_portfolio();
+
+            peak = peak.max(account.balance.current);
+            if peak > 0.0 {
+                let drawdown = ((peak - account.balance.current) / peak * 100.0) as f32;
+                max_drawdown = max_drawdown.max(drawdown);
+            }
+
+            for log in app.logs.drain(..) {
+                log::debug!("{}", log);
+            }
+
+            if (app.time.utc - self.from).num_seconds() % (PROGRESS_MINUTES * 60)
+                < self.step.num_seconds()
+            {
+                log::info!(
+                    "[{}] Balance: ${:.2} | Earnings: {:.2} | Rounds: {}",
+                    app.time.utc,
+                    account.balance.current,
+                    account.earnings,
+                    reports.len()
+                );
+            }
+
+            app.time.uptime = app.time.uptime + self.step;
+            app.time.utc += self.step;
+            app.cycles += 1;
+        }
+
+        let wins = reports.values().filter(|r| r.earnings > 0.0).count() as u64;
+        let rounds = reports.len() as u64;
+        let summary = Summary {
+            strategy: cfg.strategy.hash.clone(),
+            from_ts: self.from.timestamp_millis().to_string(),
+            to_ts: self.to.timestamp_millis().to_string(),
+            rounds,
+            trades: account.trades,
+            wins,
+            losses: rounds - wins,
+            win_rate: if rounds > 0 {
+                wins as f32 / rounds as f32 * 100.0
+            } else {
+                0.0
+            },
+            balance: account.balance.current,
+            earnings: account.earnings,
+            fee_spend: account.fee_spend,
+            max_drawdown,
+            open_positions: account.portfolio.len() as u64,
+            ts: Utc::now().timestamp_millis().to_string(),
+        };
+        summary.save(&app.db_session).await?;
+        log::info!("{}", summary.to_string());
+        Ok(summary)
+    }
+}
+
+impl Summary {
+    pub async fn save(&self, db_session: &Session) -> Result<QueryResult> {
+        let payload = serde_json::to_string_pretty(&self).unwrap();
+        let query = format!("INSERT INTO okx.backtests JSON '{}'", payload);
+        Ok(db_session.query(&*query, &[]).await?)
+    }
+}
+
+impl ToString for Summary {
+    fn to_string(&self) -> String {
+        format!(
+            "Backtest [{}] - Rounds: {} (Won: {}, Lost: {}) - Win rate: {:.2}% - Earnings: {:.2} - Fees: {:.2} - Max drawdown: {:.2}% - Open positions: {}",
+            self.strategy,
+            self.rounds,
+            self.wins,
+            self.losses,
+            self.win_rate,
+            self.earnings,
+            self.fee_spend,
+            self.max_drawdown,
+            self.open_positions
+        )
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let secs = value
+        .parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("Invalid timestamp: {}", value))?;
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp: {}", value))
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use exchange_observer::Events;
+use rskafka::{
+    client::{partition::Compression, Client, ClientBuilder},
+    record::Record,
+};
+
+use crate::prelude::*;
+
+//Bump when the payload of an event changes
+pub const EVENT_VERSION: u32 = 1;
+const TOPIC_PARTITIONS: i32 = 1;
+const TOPIC_REPLICATION_FACTOR: i16 = 1;
+const TOPIC_TIMEOUT_MS: i32 = 5000;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    TokenSelected {
+        instid: String,
+        price: f64,
+        change: f32,
+        std_deviation: f32,
+        vol: f64,
+        change24h: f32,
+        vol24h: f64,
+    },
+    PositionOpened {
+        instid: String,
+        round_id: u64,
+        price: f64,
+        size: f64,
+    },
+    OrderPlaced {
+        order: Order,
+        state: String,
+    },
+    OrderFilled {
+        order: Order,
+        fill_px: f64,
+        fill_sz: f64,
+        fee: f64,
+    },
+    PositionClosed {
+        instid: String,
+        round_id: u64,
+        reason: Option<ExitReason>,
+        buy_price: f64,
+        sell_price: f64,
+        change: f32,
+        earnings: f64,
+    },
+}
+
+#[derive(Serialize, Debug)]
+struct Envelope<'a> {
+    version: u32,
+    exchange: &'a str,
+    strategy: &'a str,
+    ts: i64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Queues scheduler events during a round and sends them to redpanda with `flush`
+#[derive(Debug)]
+pub struct Publisher {
+    client: Option<Arc<Client>>,
+    settings: Events,
+    exchange: String,
+    //Tokens in the last selection, only new ones are announced
+    selected: HashSet<String>,
+    pending: Vec<(String, Record)>,
+}
+
+impl Event {
+    fn name(&self) -> &str {
+        match self {
+            Self::TokenSelected { .. } => "token_selected",
+            Self::PositionOpened { .. } => "position_opened",
+            Self::OrderPlaced { .. } => "order_placed",
+            Self::OrderFilled { .. } => "order_filled",
+            Self::PositionClosed { .. } => "position_closed",
+        }
+    }
+
+    fn instid(&self) -> &str {
+        match self {
+            Self::TokenSelected { instid, .. }
+            | Self::PositionOpened { instid, .. }
+            | Self::PositionClosed { instid, .. } => instid,
+            Self::OrderPlaced { order, .. } | Self::OrderFilled { order, .. } => &order.inst_id,
+        }
+    }
+}
+
+impl Publisher {
+    /// Connect to redpanda and create the event topics when `events.enable` is set
+    pub async fn new(cfg: &AppConfig) -> Result<Self> {
+        let settings = cfg.events.clone().unwrap_or_default();
+        let client = if settings.enable {
+            let client = ClientBuilder::new(vec![format!("{}:{}", cfg.mq.ip, cfg.mq.port)])
+                .build()
+                .await?;
+            Publisher::create_topics(&client, &settings).await?;
+            Some(Arc::new(client))
+        } else {
+            None
+        };
+        Ok(Self {
+            client,
+            settings,
+            exchange: cfg.exchange.clone().unwrap_or_default().name,
+            selected: HashSet::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    async fn create_topics(client: &Client, settings: &Events) -> Result<()> {
+        let list = client.list_topics().await?;
+        for name in [
+            &settings.selections_topic,
+            &settings.portfolio_topic,
+            &settings.orders_topic,
+        ] {
+            if !list.iter().any(|t| t.name == *name) {
+                log::warn!("Topic {} doesn't exist. Creating it", name);
+                client
+                    .controller_client()?
+                    .create_topic(
+                        name,
+                        TOPIC_PARTITIONS,
+                        TOPIC_REPLICATION_FACTOR,
+                        TOPIC_TIMEOUT_MS,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, event: Event, strategy: &str, ts: DateTime<Utc>) -> &mut Self {
+        if self.client.is_none() {
+            return self;
+        }
+        let topic = match event {
+            Event::TokenSelected { .. } => &self.settings.selections_topic,
+            Event::PositionOpened { .. } | Event::PositionClosed { .. } => {
+                &self.settings.portfolio_topic
+            },
+            Event::OrderPlaced { .. } | Event::OrderFilled { .. } => &self.settings.orders_topic,
+        };
+        let envelope = Envelope {
+            version: EVENT_VERSION,
+            exchange: &self.exchange,
+            strategy,
+            ts: ts.timestamp_millis(),
+            event: &event,
+        };
+        let payload = match serde_json::to_vec(&envelope) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("Unable to serialize {} event: {}", event.name(), e);
+                return self;
+            },
+        };
+        let record = Record {
+            key: Some(event.instid().as_bytes().to_vec()),
+            value: Some(payload),
+            headers: BTreeMap::from([
+                ("Exchange".to_owned(), self.exchange.as_bytes().to_vec()),
+                ("Event".to_owned(), event.name().as_bytes().to_vec()),
+                (
+                    "Version".to_owned(),
+                    EVENT_VERSION.to_string().as_bytes().to_vec(),
+                ),
+            ]),
+            timestamp: OffsetDateTime::now_utc(),
+        };
+        self.pending.push((topic.clone(), record));
+        self
+    }
+
+    /// Announce tokens that entered the selection since the last round
+    pub fn select(&mut self, tokens: &[Token], strategy: &str, ts: DateTime<Utc>) -> &mut Self {
+        let selected: HashSet<String> = tokens.iter().map(|t| t.instid.clone()).collect();
+        let new_tokens: Vec<&Token> = tokens
+            .iter()
+            .filter(|t| !self.selected.contains(&t.instid))
+            .collect();
+        for t in new_tokens {
+            self.push(
+                Event::TokenSelected {
+                    instid: t.instid.clone(),
+                    price: t.price,
+                    change: t.change,
+                    std_deviation: t.std_deviation,
+                    vol: t.vol,
+                    change24h: t.change24h,
+                    vol24h: t.vol24h,
+                },
+                strategy,
+                ts,
+            );
+        }
+        self.selected = selected;
+        self
+    }
+
+    /// Send the queued events. Events that fail are dropped
+    pub async fn flush(&mut self) -> Result<()> {
+        let Some(client) = self.client.as_ref() else {
+            return Ok(());
+        };
+        let mut topics: BTreeMap<String, Vec<Record>> = BTreeMap::new();
+        for (topic, record) in self.pending.drain(..) {
+            topics.entry(topic).or_default().push(record);
+        }
+        for (topic, records) in topics {
+            client
+                .partition_client(topic, 0)?
+                .produce(records, Compression::Lz4)
+                .await?;
+        }
+        Ok(())
+    }
+}
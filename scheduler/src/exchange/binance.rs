@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+
+use super::{Broker, OrderResponse};
+use crate::prelude::*;
+
+pub const BASE_URL: &str = "https://api.binance.com";
+pub const ORDER_ENDPOINT: &str = "/api/v3/order";
+pub const ACCOUNT_ENDPOINT: &str = "/api/v3/account";
+pub const EXCHANGE_INFO_ENDPOINT: &str = "/api/v3/exchangeInfo";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinanceOrder {
+    pub symbol: String,
+    pub order_id: i64,
+    pub client_order_id: String,
+    pub status: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinanceError {
+    pub code: i64,
+    pub msg: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinanceAccount {
+    pub balances: Vec<BinanceBalance>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinanceBalance {
+    pub asset: String,
+    pub free: String,
+    pub locked: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinanceExchangeInfo {
+    pub symbols: Vec<BinanceSymbol>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinanceSymbol {
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub filters: Vec<BinanceFilter>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BinanceFilter {
+    pub filter_type: String,
+    pub tick_size: String,
+    pub step_size: String,
+    pub min_qty: String,
+}
+
+#[derive(Debug)]
+pub struct Binance;
+
+impl Binance {
+    /// BTC-USDT -> BTCUSDT
+    pub fn to_symbol(inst_id: &str) -> String {
+        inst_id.replace('-', "").to_uppercase()
+    }
+
+    pub fn parse_state(status: &str) -> OrderState {
+        match status {
+            "NEW" => OrderState::Live,
+            "PARTIALLY_FILLED" => OrderState::PartiallyFilled,
+            "FILLED" => OrderState::Filled,
+            _ => OrderState::Cancelled,
+        }
+    }
+
+    /// Append timestamp and signature to the query string
+    fn signed_query(query: &str, auth: &Authentication) -> Result<String> {
+        let query = format!(
+            "{}&timestamp={}",
+            query,
+            OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000
+        );
+        let signature = auth.sign_query(&query)?;
+        Ok(format!("{query}&signature={signature}"))
+    }
+}
+
+#[async_trait]
+impl Broker for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    async fn place_order(
+        &self,
+        order: &Order,
+        auth: &Authentication,
+    ) -> Result<Option<OrderResponse>> {
+        let mut query = format!(
+            "symbol={}&side={}&newClientOrderId={}&quantity={}",
+            Binance::to_symbol(&order.inst_id),
+            order.side.to_string().to_uppercase(),
+            order.cl_ord_id,
+            order.sz
+        );
+        match order.ord_type.as_str() {
+            "market" => query.push_str("&type=MARKET"),
+            ord_type => {
+                let time_in_force = match ord_type {
+                    "ioc" => "IOC",
+                    "fok" => "FOK",
+                    _ => "GTC",
+                };
+                query.push_str(&format!(
+                    "&type=LIMIT&timeInForce={}&price={}",
+                    time_in_force, order.px
+                ));
+            },
+        }
+        let query = Binance::signed_query(&query, auth)?;
+
+        let res = reqwest::Client::new()
+            .post(format!("{BASE_URL}{ORDER_ENDPOINT}?{query}"))
+            .header("X-MBX-APIKEY", &auth.access_key)
+            .send()
+            .await?;
+
+        let success = res.status().is_success();
+        let body = res.text().await?;
+        if success {
+            match serde_json::from_str::<BinanceOrder>(&body) {
+                Ok(res) => Ok(Some(OrderResponse {
+                    code: "0".to_string(),
+                    ord_id: res.order_id.to_string(),
+                    msg: res.status,
+                })),
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    Ok(None)
+                },
+            }
+        } else {
+            log::error!("{}", body);
+            Ok(serde_json::from_str::<BinanceError>(&body)
+                .ok()
+                .map(|e| OrderResponse {
+                    code: e.code.to_string(),
+                    ord_id: String::new(),
+                    msg: e.msg,
+                }))
+        }
+    }
+
+    async fn order_state(&self, order: &Order, auth: &Authentication) -> Result<OrderState> {
+        let query = Binance::signed_query(
+            &format!(
+                "symbol={}&orderId={}",
+                Binance::to_symbol(&order.inst_id),
+                order.id
+            ),
+            auth,
+        )?;
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{ORDER_ENDPOINT}?{query}"))
+            .header("X-MBX-APIKEY", &auth.access_key)
+            .send()
+            .await?
+            .json::<BinanceOrder>()
+            .await?;
+        Ok(Binance::parse_state(&res.status))
+    }
+
+    async fn cancel_order(&self, order: &Order, auth: &Authentication) -> Result<bool> {
+        let query = Binance::signed_query(
+            &format!(
+                "symbol={}&orderId={}",
+                Binance::to_symbol(&order.inst_id),
+                order.id
+            ),
+            auth,
+        )?;
+        let res = reqwest::Client::new()
+            .delete(format!("{BASE_URL}{ORDER_ENDPOINT}?{query}"))
+            .header("X-MBX-APIKEY", &auth.access_key)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            log::warn!(
+                "Cancel failed for order {}: {}",
+                order.id,
+                res.text().await?
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    //Spot orders can't be re-priced, they are cancelled instead
+    async fn amend_order(&self, _order: &Order, _px: &str, _auth: &Authentication) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn instruments(&self) -> Result<Vec<Instrument>> {
+        let res = reqwest::Client::new()
+            .get(format!(
+                "{BASE_URL}{EXCHANGE_INFO_ENDPOINT}?permissions=SPOT"
+            ))
+            .send()
+            .await?
+            .json::<BinanceExchangeInfo>()
+            .await?;
+        Ok(res
+            .symbols
+            .into_iter()
+            .filter(|s| s.status == "TRADING")
+            .map(|s| {
+                let mut instrument = Instrument {
+                    instid: format!("{}-{}", s.base_asset, s.quote_asset),
+                    ..Default::default()
+                };
+                for f in s.filters {
+                    match f.filter_type.as_str() {
+                        "PRICE_FILTER" => {
+                            instrument.tick_sz = f.tick_size.parse().unwrap_or_default()
+                        },
+                        "LOT_SIZE" => {
+                            instrument.lot_sz = f.step_size.parse().unwrap_or_default();
+                            instrument.min_sz = f.min_qty.parse().unwrap_or_default();
+                        },
+                        _ => (),
+                    }
+                }
+                instrument
+            })
+            .collect())
+    }
+
+    async fn balance(&self, ccy: &str, auth: &Authentication) -> Result<f64> {
+        log::info!("Retrieving balance of: {}", ccy);
+        let query = Binance::signed_query("omitZeroBalances=true", auth)?;
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{ACCOUNT_ENDPOINT}?{query}"))
+            .header("X-MBX-APIKEY", &auth.access_key)
+            .send()
+            .await?
+            .json::<BinanceAccount>()
+            .await?;
+
+        match res.balances.iter().find(|b| b.asset == ccy) {
+            Some(balance) => balance
+                .free
+                .parse::<f64>()
+                .map_err(|_| anyhow::anyhow!("Failed to parse balance")),
+            //Zero balances are omitted from the response
+            None => Ok(0.0),
+        }
+    }
+}
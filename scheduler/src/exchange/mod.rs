@@ -0,0 +1,92 @@
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::prelude::*;
+
+pub mod binance;
+pub mod okx;
+pub mod okx_ws;
+pub mod paper;
+
+/// Exchange reply to an order placement, normalized across exchanges.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderResponse {
+    //"0" when the order was accepted
+    pub code: String,
+    pub ord_id: String,
+    pub msg: String,
+}
+
+impl OrderResponse {
+    pub fn is_success(&self) -> bool {
+        self.code == "0"
+    }
+}
+
+/// State of a take-profit/stop-loss kept by the exchange
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExitState {
+    Pending,
+    //ord_id is the sell order placed by the trigger
+    Triggered { ord_id: String, take_profit: bool },
+    Cancelled,
+}
+
+/// Trading side of an exchange: order placement, order status and balances.
+/// Instrument ids are always in `BASE-QUOTE` format (ex: BTC-USDT).
+#[async_trait]
+pub trait Broker: Send + Sync + Debug {
+    fn name(&self) -> &'static str;
+    /// Send the order to the exchange.
+    /// Returns `None` if the exchange didn't reply with a valid response
+    async fn place_order(
+        &self,
+        order: &Order,
+        auth: &Authentication,
+    ) -> Result<Option<OrderResponse>>;
+    async fn order_state(&self, order: &Order, auth: &Authentication) -> Result<OrderState>;
+    /// Cancel a live order. Returns false if the exchange rejected it (ex: already filled)
+    async fn cancel_order(&self, order: &Order, auth: &Authentication) -> Result<bool>;
+    /// Move a live order to a new price. Returns false if the exchange rejected it
+    async fn amend_order(&self, order: &Order, px: &str, auth: &Authentication) -> Result<bool>;
+    /// Trading rules (tick size, lot size, minimum size) of the spot instruments
+    async fn instruments(&self) -> Result<Vec<Instrument>>;
+    /// Available balance of a currency (ex: BTC)
+    async fn balance(&self, ccy: &str, auth: &Authentication) -> Result<f64>;
+    /// Place a take-profit/stop-loss sell (OCO) of `sz`, filled at market once triggered.
+    /// Returns its id, `None` when the exchange doesn't support it
+    async fn place_exit(
+        &self,
+        _inst_id: &str,
+        _sz: &str,
+        _tp_px: &str,
+        _sl_px: &str,
+        _auth: &Authentication,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
+    async fn cancel_exit(&self, _inst_id: &str, _id: &str, _auth: &Authentication) -> Result<bool> {
+        Ok(false)
+    }
+    async fn exit_state(
+        &self,
+        _inst_id: &str,
+        _id: &str,
+        _auth: &Authentication,
+    ) -> Result<ExitState> {
+        Ok(ExitState::Pending)
+    }
+    /// Average price, filled size and fee (USDT) pushed by the exchange, if any
+    fn fill(&self, _order: &Order) -> Option<(f64, f64, f64)> {
+        None
+    }
+}
+
+pub fn from_name(name: &str) -> Result<Arc<dyn Broker>> {
+    match name.to_lowercase().as_str() {
+        "okx" => Ok(Arc::new(okx::Okx)),
+        "binance" => Ok(Arc::new(binance::Binance)),
+        _ => Err(anyhow::anyhow!("Unsupported exchange: {}", name)),
+    }
+}
@@ -0,0 +1,455 @@
+use async_trait::async_trait;
+
+use super::{Broker, ExitState, OrderResponse};
+use crate::prelude::*;
+
+pub const BASE_URL: &str = "https://www.okx.com";
+pub const ORDERS_ENDPOINT: &str = "/api/v5/trade/order";
+pub const BALANCE_ENDPOINT: &str = "/api/v5/account/balance";
+pub const CANCEL_ENDPOINT: &str = "/api/v5/trade/cancel-order";
+pub const AMEND_ENDPOINT: &str = "/api/v5/trade/amend-order";
+pub const INSTRUMENTS_ENDPOINT: &str = "/api/v5/public/instruments";
+pub const ALGO_ENDPOINT: &str = "/api/v5/trade/order-algo";
+pub const CANCEL_ALGO_ENDPOINT: &str = "/api/v5/trade/cancel-algos";
+pub const ALGO_HISTORY_ENDPOINT: &str = "/api/v5/trade/orders-algo-history";
+
+pub type OkxAccountBalanceResponse = OkxApiResponse<OkxAccountBalance>;
+pub type OkxOrderResponse = OkxApiResponse<OkxOrder>;
+pub type OkxTimeResponse = OkxApiResponse<OkxTime>;
+pub type OkxOrderDetailsResponse = OkxApiResponse<OkxOrderDetails>;
+pub type OkxInstrumentsResponse = OkxApiResponse<OkxInstrument>;
+pub type OkxAlgoOrderResponse = OkxApiResponse<OkxAlgoOrder>;
+pub type OkxAlgoDetailsResponse = OkxApiResponse<OkxAlgoDetails>;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OkxApiResponse<T> {
+    pub code: String,
+    pub data: Vec<T>,
+    pub msg: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrderDetails {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub ccy: String,
+    pub ord_id: String,
+    pub cl_ord_id: String,
+    pub tag: String,
+    pub px: String,
+    pub sz: String,
+    pub pnl: String,
+    pub ord_type: String,
+    pub side: String,
+    pub pos_side: String,
+    pub td_mode: String,
+    pub acc_fill_sz: String,
+    pub fill_px: String,
+    pub trade_id: String,
+    pub fill_sz: String,
+    pub fill_time: String,
+    pub state: String,
+    pub avg_px: String,
+    pub lever: String,
+    pub tp_trigger_px: String,
+    pub tp_trigger_px_type: String,
+    pub tp_ord_px: String,
+    pub sl_trigger_px: String,
+    pub sl_trigger_px_type: String,
+    pub sl_ord_px: String,
+    pub fee_ccy: String,
+    pub fee: String,
+    pub rebate_ccy: String,
+    pub rebate: String,
+    pub tgt_ccy: String,
+    pub category: String,
+    pub reduce_only: String,
+    pub cancel_source: String,
+    pub cancel_source_reason: String,
+    pub quick_mgn_type: String,
+    pub algo_cl_ord_id: String,
+    pub algo_id: String,
+    pub u_time: String,
+    pub c_time: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAccountBalance {
+    pub adj_eq: String,
+    pub details: Vec<OkxAccountBalanceDetail>,
+    pub imr: String,
+    pub iso_eq: String,
+    pub mgn_ratio: String,
+    pub mmr: String,
+    pub notional_usd: String,
+    pub ord_froz: String,
+    pub total_eq: String,
+    pub u_time: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAccountBalanceDetail {
+    pub avail_bal: String,
+    pub avail_eq: String,
+    pub cash_bal: String,
+    pub ccy: String,
+    pub cross_liab: String,
+    pub dis_eq: String,
+    pub eq: String,
+    pub eq_usd: String,
+    pub frozen_bal: String,
+    pub interest: String,
+    pub iso_eq: String,
+    pub iso_liab: String,
+    pub iso_upl: String,
+    pub liab: String,
+    pub max_loan: String,
+    pub mgn_ratio: String,
+    pub notional_lever: String,
+    pub ord_frozen: String,
+    pub twap: String,
+    pub u_time: String,
+    pub upl: String,
+    pub upl_liab: String,
+    pub stgy_eq: String,
+    pub spot_in_use_amt: String,
+}
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrder {
+    pub cl_ord_id: String,
+    pub ord_id: String,
+    pub s_code: String,
+    pub s_msg: String,
+    pub tag: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAlgoOrder {
+    pub algo_id: String,
+    pub s_code: String,
+    pub s_msg: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OkxAlgoDetails {
+    pub algo_id: String,
+    pub state: String,
+    //Order placed once triggered
+    pub ord_id: String,
+    //"tp" or "sl"
+    pub actual_side: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxInstrument {
+    pub inst_id: String,
+    pub tick_sz: String,
+    pub lot_sz: String,
+    pub min_sz: String,
+    pub state: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OkxTime {
+    pub ts: String,
+}
+
+pub async fn get_time() -> Result<i64> {
+    let res = reqwest::Client::new()
+        .get(format!("{BASE_URL}/api/v5/public/time"))
+        .send()
+        .await?
+        .json::<OkxTimeResponse>()
+        .await?;
+    Ok(res.data[0].ts.parse::<i64>()?)
+}
+
+#[derive(Debug)]
+pub struct Okx;
+
+impl Okx {
+    /// Signed POST to a trade endpoint. True if the order operation succeeded
+    async fn post_trade(
+        endpoint: &str,
+        body: serde_json::Value,
+        auth: &Authentication,
+    ) -> Result<bool> {
+        let res: OkxOrderResponse = Okx::post(endpoint, body, auth).await?;
+        let data = res.data.get(0).cloned().unwrap_or_default();
+        if res.code != "0" || data.s_code != "0" {
+            log::warn!(
+                "{} failed for order {}: {} {}",
+                endpoint,
+                data.ord_id,
+                res.msg,
+                data.s_msg
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        endpoint: &str,
+        body: serde_json::Value,
+        auth: &Authentication,
+    ) -> Result<T> {
+        let json_body = body.to_string();
+        let signed = auth.sign(
+            "POST",
+            endpoint,
+            OffsetDateTime::now_utc(),
+            false,
+            &json_body,
+        )?;
+        let res = reqwest::Client::new()
+            .post(format!("{BASE_URL}{endpoint}"))
+            .header("OK-ACCESS-KEY", &auth.access_key)
+            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
+            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
+            .header("OK-ACCESS-SIGN", signed.signature.as_str())
+            .header("Content-Type", "application/json")
+            .body(json_body)
+            .send()
+            .await?
+            .json::<T>()
+            .await?;
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl Broker for Okx {
+    fn name(&self) -> &'static str {
+        "okx"
+    }
+
+    async fn place_order(
+        &self,
+        order: &Order,
+        auth: &Authentication,
+    ) -> Result<Option<OrderResponse>> {
+        let json_body = serde_json::to_string(&order)?;
+        let signed = auth.sign(
+            "POST",
+            ORDERS_ENDPOINT,
+            OffsetDateTime::now_utc(),
+            false,
+            &json_body,
+        )?;
+
+        let res = reqwest::Client::new()
+            .post(format!("{BASE_URL}{ORDERS_ENDPOINT}"))
+            .header("OK-ACCESS-KEY", auth.access_key.clone())
+            .header("OK-ACCESS-PASSPHRASE", auth.passphrase.clone())
+            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
+            .header("OK-ACCESS-SIGN", signed.signature.as_str())
+            .json(&order)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let body = res.text().await?;
+            log::error!("{}", body);
+            return Ok(None);
+        }
+
+        match res.json::<OkxOrderResponse>().await {
+            Ok(res) => {
+                let data = res.data.get(0).cloned().unwrap_or_default();
+                Ok(Some(OrderResponse {
+                    //Operation failures are reported per order in s_code
+                    code: if res.code == "0" {
+                        data.s_code
+                    } else {
+                        res.code
+                    },
+                    ord_id: data.ord_id,
+                    msg: if data.s_msg.is_empty() {
+                        res.msg
+                    } else {
+                        data.s_msg
+                    },
+                }))
+            },
+            Err(e) => {
+                log::error!("{:?}", e);
+                Ok(None)
+            },
+        }
+    }
+
+    async fn order_state(&self, order: &Order, auth: &Authentication) -> Result<OrderState> {
+        let inst_id = order.inst_id.clone();
+        let query = &format!("?ordId={ord_id}&instId={inst_id}", ord_id = order.id);
+        let signed = auth.sign(
+            "GET",
+            ORDERS_ENDPOINT,
+            OffsetDateTime::now_utc(),
+            false,
+            query,
+        )?;
+
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{ORDERS_ENDPOINT}{query}"))
+            .header("OK-ACCESS-KEY", &auth.access_key)
+            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
+            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
+            .header("OK-ACCESS-SIGN", signed.signature.as_str())
+            .send()
+            .await?
+            .json::<OkxOrderDetailsResponse>()
+            .await?;
+        let order_state =
+            OrderState::from_str(&res.data[0].state).unwrap_or(OrderState::Cancelled);
+        Ok(order_state)
+    }
+
+    async fn cancel_order(&self, order: &Order, auth: &Authentication) -> Result<bool> {
+        let body = serde_json::json!({ "instId": order.inst_id, "ordId": order.id });
+        Okx::post_trade(CANCEL_ENDPOINT, body, auth).await
+    }
+
+    async fn amend_order(&self, order: &Order, px: &str, auth: &Authentication) -> Result<bool> {
+        let body = serde_json::json!({ "instId": order.inst_id, "ordId": order.id, "newPx": px });
+        Okx::post_trade(AMEND_ENDPOINT, body, auth).await
+    }
+
+    async fn place_exit(
+        &self,
+        inst_id: &str,
+        sz: &str,
+        tp_px: &str,
+        sl_px: &str,
+        auth: &Authentication,
+    ) -> Result<Option<String>> {
+        let body = serde_json::json!({
+            "instId": inst_id,
+            "tdMode": "cash",
+            "side": "sell",
+            "ordType": "oco",
+            "sz": sz,
+            "tpTriggerPx": tp_px,
+            "tpOrdPx": "-1",
+            "slTriggerPx": sl_px,
+            "slOrdPx": "-1",
+        });
+        let res: OkxAlgoOrderResponse = Okx::post(ALGO_ENDPOINT, body, auth).await?;
+        let data = res.data.get(0).cloned().unwrap_or_default();
+        if res.code != "0" || data.s_code != "0" {
+            log::warn!(
+                "[{}] Unable to place exit orders: {} {}",
+                inst_id,
+                res.msg,
+                data.s_msg
+            );
+            return Ok(None);
+        }
+        Ok(Some(data.algo_id))
+    }
+
+    async fn cancel_exit(&self, inst_id: &str, id: &str, auth: &Authentication) -> Result<bool> {
+        let body = serde_json::json!([{ "instId": inst_id, "algoId": id }]);
+        let res: OkxAlgoOrderResponse = Okx::post(CANCEL_ALGO_ENDPOINT, body, auth).await?;
+        let data = res.data.get(0).cloned().unwrap_or_default();
+        Ok(res.code == "0" && data.s_code == "0")
+    }
+
+    async fn exit_state(
+        &self,
+        _inst_id: &str,
+        id: &str,
+        auth: &Authentication,
+    ) -> Result<ExitState> {
+        let query = &format!("?ordType=oco&algoId={id}");
+        let signed = auth.sign(
+            "GET",
+            ALGO_HISTORY_ENDPOINT,
+            OffsetDateTime::now_utc(),
+            false,
+            query,
+        )?;
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{ALGO_HISTORY_ENDPOINT}{query}"))
+            .header("OK-ACCESS-KEY", &auth.access_key)
+            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
+            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
+            .header("OK-ACCESS-SIGN", signed.signature.as_str())
+            .send()
+            .await?
+            .json::<OkxAlgoDetailsResponse>()
+            .await?;
+        //Pending algo orders aren't in the history yet
+        let state = match res.data.into_iter().next() {
+            Some(algo) if algo.state == "effective" => ExitState::Triggered {
+                ord_id: algo.ord_id,
+                take_profit: algo.actual_side == "tp",
+            },
+            Some(algo) if algo.state != "live" => ExitState::Cancelled,
+            _ => ExitState::Pending,
+        };
+        Ok(state)
+    }
+
+    async fn instruments(&self) -> Result<Vec<Instrument>> {
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{INSTRUMENTS_ENDPOINT}?instType=SPOT"))
+            .send()
+            .await?
+            .json::<OkxInstrumentsResponse>()
+            .await?;
+        Ok(res
+            .data
+            .into_iter()
+            .filter(|i| i.state == "live")
+            .map(|i| Instrument {
+                instid: i.inst_id,
+                tick_sz: i.tick_sz.parse().unwrap_or_default(),
+                lot_sz: i.lot_sz.parse().unwrap_or_default(),
+                min_sz: i.min_sz.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn balance(&self, ccy: &str, auth: &Authentication) -> Result<f64> {
+        let query = &format!("?ccy={ccy}");
+        let signed = auth.sign(
+            "GET",
+            BALANCE_ENDPOINT,
+            OffsetDateTime::now_utc(),
+            false,
+            query,
+        )?;
+        log::info!("Retrieving balance of: {}", ccy);
+        let res = reqwest::Client::new()
+            .get(format!("{BASE_URL}{BALANCE_ENDPOINT}{query}"))
+            .header("OK-ACCESS-KEY", &auth.access_key)
+            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
+            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
+            .header("OK-ACCESS-SIGN", signed.signature.as_str())
+            .send()
+            .await?
+            .json::<OkxAccountBalanceResponse>()
+            .await?;
+
+        log::info!("Response: {}", serde_json::to_string_pretty(&res)?);
+
+        let balance =
+            if let Some(balance) = res.data.get(0).and_then(|balance| balance.details.get(0)) {
+                balance
+                    .avail_bal
+                    .parse::<f64>()
+                    .map_err(|_| anyhow::anyhow!("Failed to parse balance"))?
+            } else {
+                return Err(anyhow::anyhow!("Failed to retrieve balance details"));
+            };
+        Ok(balance.to_owned())
+    }
+}
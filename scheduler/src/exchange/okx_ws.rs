@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use super::{Broker, ExitState, OrderResponse};
+use crate::prelude::*;
+
+pub const PRIVATE_ENDPOINT: &str = "wss://ws.okx.com:8443/ws/v5/private";
+const LOGIN_PATH: &str = "/users/self/verify";
+//okx closes connections without traffic for 30 seconds
+const PING_SECS: u64 = 25;
+const RECONNECT_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct OrderPush {
+    ord_id: String,
+    state: String,
+    avg_px: String,
+    acc_fill_sz: String,
+    fee: String,
+    fee_ccy: String,
+    fill_time: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct BalancePush {
+    ccy: String,
+    avail_bal: String,
+    u_time: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct AccountPush {
+    details: Vec<BalancePush>,
+}
+
+/// Latest order state and fills pushed by the `orders` channel
+#[derive(Debug, Clone, Default)]
+pub struct OrderUpdate {
+    pub state: OrderState,
+    pub avg_px: f64,
+    pub acc_fill_sz: f64,
+    //USDT
+    pub fee: f64,
+}
+
+#[derive(Debug, Default)]
+struct Feed {
+    orders: HashMap<String, OrderUpdate>,
+    //Available balance and its update time (ms)
+    balances: HashMap<String, (f64, i64)>,
+    //Time of the latest pushed fill (ms), balances updated before it are stale
+    last_fill: i64,
+}
+
+/// okx broker fed by the private websocket (`orders` and `account` channels).
+/// Orders and currencies without pushes since the last (re)connection, or balances not
+/// pushed since the latest fill, fall back to REST.
+#[derive(Debug)]
+pub struct Streamed {
+    rest: Arc<dyn Broker>,
+    feed: Arc<RwLock<Feed>>,
+}
+
+impl Streamed {
+    pub fn new(rest: Arc<dyn Broker>, auth: &Authentication) -> Self {
+        let feed = Arc::new(RwLock::new(Feed::default()));
+        tokio::spawn(run(auth.clone(), feed.clone()));
+        Self { rest, feed }
+    }
+}
+
+#[async_trait]
+impl Broker for Streamed {
+    fn name(&self) -> &'static str {
+        self.rest.name()
+    }
+
+    async fn place_order(
+        &self,
+        order: &Order,
+        auth: &Authentication,
+    ) -> Result<Option<OrderResponse>> {
+        self.rest.place_order(order, auth).await
+    }
+
+    async fn order_state(&self, order: &Order, auth: &Authentication) -> Result<OrderState> {
+        let pushed = self
+            .feed
+            .read()
+            .unwrap()
+            .orders
+            .get(&order.id)
+            .map(|u| u.state.clone());
+        match pushed {
+            Some(state) => Ok(state),
+            None => self.rest.order_state(order, auth).await,
+        }
+    }
+
+    async fn cancel_order(&self, order: &Order, auth: &Authentication) -> Result<bool> {
+        self.rest.cancel_order(order, auth).await
+    }
+
+    async fn amend_order(&self, order: &Order, px: &str, auth: &Authentication) -> Result<bool> {
+        self.rest.amend_order(order, px, auth).await
+    }
+
+    async fn place_exit(
+        &self,
+        inst_id: &str,
+        sz: &str,
+        tp_px: &str,
+        sl_px: &str,
+        auth: &Authentication,
+    ) -> Result<Option<String>> {
+        self.rest.place_exit(inst_id, sz, tp_px, sl_px, auth).await
+    }
+
+    async fn cancel_exit(&self, inst_id: &str, id: &str, auth: &Authentication) -> Result<bool> {
+        self.rest.cancel_exit(inst_id, id, auth).await
+    }
+
+    async fn exit_state(
+        &self,
+        inst_id: &str,
+        id: &str,
+        auth: &Authentication,
+    ) -> Result<ExitState> {
+        self.rest.exit_state(inst_id, id, auth).await
+    }
+
+    async fn instruments(&self) -> Result<Vec<Instrument>> {
+        self.rest.instruments().await
+    }
+
+    async fn balance(&self, ccy: &str, auth: &Authentication) -> Result<f64> {
+        let pushed = {
+            let feed = self.feed.read().unwrap();
+            feed.balances
+                .get(ccy)
+                .filter(|(_, ts)| *ts >= feed.last_fill)
+                .map(|(balance, _)| *balance)
+        };
+        match pushed {
+            Some(balance) => Ok(balance),
+            None => self.rest.balance(ccy, auth).await,
+        }
+    }
+
+    fn fill(&self, order: &Order) -> Option<(f64, f64, f64)> {
+        self.feed
+            .read()
+            .unwrap()
+            .orders
+            .get(&order.id)
+            .filter(|u| u.acc_fill_sz > 0.0)
+            .map(|u| (u.avg_px, u.acc_fill_sz, u.fee))
+    }
+}
+
+async fn run(auth: Authentication, feed: Arc<RwLock<Feed>>) {
+    loop {
+        if let Err(e) = listen(&auth, &feed).await {
+            log::warn!("okx private websocket disconnected: {}", e);
+        }
+        //Pushes missed while disconnected are recovered through REST
+        *feed.write().unwrap() = Feed::default();
+        tokio::time::sleep(time::Duration::from_secs(RECONNECT_SECS)).await;
+    }
+}
+
+async fn listen(auth: &Authentication, feed: &RwLock<Feed>) -> Result<()> {
+    let (ws, _) = connect_async(PRIVATE_ENDPOINT).await?;
+    let (mut write, mut read) = ws.split();
+
+    let signed = auth.sign("GET", LOGIN_PATH, OffsetDateTime::now_utc(), true, "")?;
+    let login = json!({
+        "op": "login",
+        "args": [{
+            "apiKey": auth.access_key,
+            "passphrase": auth.passphrase,
+            "timestamp": signed.timestamp,
+            "sign": signed.signature,
+        }]
+    });
+    write.send(Message::Text(login.to_string())).await?;
+
+    let mut ping = tokio::time::interval(time::Duration::from_secs(PING_SECS));
+    loop {
+        tokio::select! {
+            _ = ping.tick() => write.send(Message::Text("ping".to_string())).await?,
+            msg = read.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => {
+                        return Err(anyhow::anyhow!("connection closed"))
+                    },
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+                if text == "pong" {
+                    continue;
+                }
+                let res: Value = serde_json::from_str(&text)?;
+                match res["event"].as_str() {
+                    Some("login") if res["code"] == "0" => {
+                        log::info!("Logged in to the okx private websocket");
+                        let subscribe = json!({
+                            "op": "subscribe",
+                            "args": [
+                                { "channel": "orders", "instType": "SPOT" },
+                                { "channel": "account" },
+                            ]
+                        });
+                        write.send(Message::Text(subscribe.to_string())).await?;
+                    },
+                    Some("login") | Some("error") => {
+                        return Err(anyhow::anyhow!("{} {}", res["code"], res["msg"]))
+                    },
+                    Some(_) => continue,
+                    None => process(&res, feed)?,
+                }
+            },
+        }
+    }
+}
+
+fn process(res: &Value, feed: &RwLock<Feed>) -> Result<()> {
+    let mut feed = feed.write().unwrap();
+    match res["arg"]["channel"].as_str() {
+        Some("orders") => {
+            for push in serde_json::from_value::<Vec<OrderPush>>(res["data"].clone())? {
+                let avg_px = push.avg_px.parse::<f64>().unwrap_or_default();
+                //Negative when charged, buys pay it in the base currency
+                let fee = -push.fee.parse::<f64>().unwrap_or_default();
+                let fee = if push.fee_ccy == "USDT" {
+                    fee
+                } else {
+                    fee * avg_px
+                };
+                if let Ok(fill_time) = push.fill_time.parse::<i64>() {
+                    feed.last_fill = feed.last_fill.max(fill_time);
+                }
+                feed.orders.insert(
+                    push.ord_id,
+                    OrderUpdate {
+                        state: OrderState::from_str(&push.state).unwrap_or(OrderState::Cancelled),
+                        avg_px,
+                        acc_fill_sz: push.acc_fill_sz.parse::<f64>().unwrap_or_default(),
+                        fee,
+                    },
+                );
+            }
+        },
+        Some("account") => {
+            for push in serde_json::from_value::<Vec<AccountPush>>(res["data"].clone())? {
+                for detail in push.details {
+                    if let Ok(balance) = detail.avail_bal.parse::<f64>() {
+                        let ts = detail.u_time.parse::<i64>().unwrap_or_default();
+                        feed.balances.insert(detail.ccy, (balance, ts));
+                    }
+                }
+            }
+        },
+        _ => (),
+    }
+    Ok(())
+}
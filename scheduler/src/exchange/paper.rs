@@ -0,0 +1,152 @@
+use crate::prelude::*;
+
+/// Market state used to fill paper orders: best levels first on each side, (price, size)
+#[derive(Debug, Clone, Default)]
+pub struct Quote {
+    pub asks: Vec<(f64, f64)>,
+    pub bids: Vec<(f64, f64)>,
+}
+
+impl Quote {
+    /// Book levels when the books channel is stored, top of book from tickers otherwise
+    pub fn new(
+        ticker: (f64, f64, f64, f64),
+        book: Option<(Vec<OrderEntry>, Vec<OrderEntry>)>,
+    ) -> Self {
+        let (ask_px, ask_sz, bid_px, bid_sz) = ticker;
+        let levels = |side: &[OrderEntry]| -> Vec<(f64, f64)> {
+            side.iter().map(|e| (e.price, e.quantity)).collect()
+        };
+        match book {
+            Some((asks, bids)) if !asks.is_empty() && !bids.is_empty() => Self {
+                asks: levels(&asks),
+                bids: levels(&bids),
+            },
+            _ => Self {
+                asks: vec![(ask_px, ask_sz)],
+                bids: vec![(bid_px, bid_sz)],
+            },
+        }
+    }
+}
+
+/// Decides paper order outcomes from stored market data instead of the exchange.
+#[derive(Debug, Clone)]
+pub struct FillSimulator {
+    pub taker_fee: f64,
+    pub maker_fee: f64,
+    //Extra % applied against the order on top of the walked levels
+    pub slippage: f64,
+}
+
+impl FillSimulator {
+    pub fn new(exchange: &Exchange) -> Self {
+        Self {
+            taker_fee: exchange.taker_fee,
+            maker_fee: exchange.maker_fee,
+            slippage: exchange.paper_slippage.unwrap_or(0.0),
+        }
+    }
+
+    /// Update the order state with the simulated fill.
+    /// Market orders take liquidity until filled, IOC orders take the levels within
+    /// their limit price and cancel the rest, limit orders stay live until the price crosses
+    /// and keep filling every round until complete (or cancelled after `order_ttl`).
+    /// Fills follow the instrument lot size and orders under its minimum size fail.
+    pub fn fill(&self, order: &mut Order, quote: Option<&Quote>, instrument: Option<&Instrument>) {
+        let quote = match quote {
+            Some(quote) => quote,
+            //No market data to fill against
+            None => {
+                if !matches!(order.ord_type.as_str(), "limit" | "post_only") {
+                    order.state = OrderState::Cancelled;
+                }
+                return;
+            },
+        };
+        let (size, limit) = match (order.sz.parse::<f64>(), order.px.parse::<f64>()) {
+            (Ok(size), Ok(limit)) if instrument.map_or(size > 0.0, |i| i.is_valid_size(size)) => {
+                (size, limit)
+            },
+            _ => {
+                order.state = OrderState::Failed;
+                return;
+            },
+        };
+        let levels = match order.side {
+            Side::Buy => &quote.asks,
+            Side::Sell => &quote.bids,
+        };
+        let crosses = |price: f64| match order.side {
+            Side::Buy => price <= limit,
+            Side::Sell => price >= limit,
+        };
+
+        let (filled, notional, taker) = match order.ord_type.as_str() {
+            "market" => {
+                let (filled, notional) = walk(levels, size, |_| true);
+                //Assume the missing depth is available at the last known level
+                let last_px = levels.last().map(|l| l.0).unwrap_or(limit);
+                (size, notional + (size - filled) * last_px, true)
+            },
+            //Orders are checked a round after being placed, so resting orders are makers
+            "limit" | "post_only" => {
+                let (filled, _) = walk(levels, size - order.fill_sz, crosses);
+                if filled <= 0.0 {
+                    return;
+                }
+                (filled, filled * limit, false)
+            },
+            _ => {
+                let (filled, notional) = walk(levels, size, crosses);
+                (filled, notional, true)
+            },
+        };
+
+        //Round down to the lot size, keeping the average price
+        let (filled, notional) = match instrument {
+            Some(i) if filled > 0.0 => {
+                let rounded = i.round_size(filled);
+                (rounded, notional * rounded / filled)
+            },
+            _ => (filled, notional),
+        };
+        if filled <= 0.0 {
+            order.state = OrderState::Cancelled;
+            return;
+        }
+        let (slippage, fee_rate) = if taker {
+            (1.0 + self.slippage / 100.0, self.taker_fee)
+        } else {
+            (1.0, self.maker_fee)
+        };
+        let avg_px = match order.side {
+            Side::Buy => (notional / filled) * slippage,
+            Side::Sell => (notional / filled) / slippage,
+        };
+        let total = order.fill_sz + filled;
+        order.fill_px = (order.fill_px * order.fill_sz + avg_px * filled) / total;
+        order.fill_sz = total;
+        order.fee += calculate_fees(avg_px * filled, fee_rate);
+        order.state = if !taker && total < size {
+            OrderState::PartiallyFilled
+        } else {
+            OrderState::Filled
+        };
+    }
+}
+
+//Take liquidity from the levels until size is filled. Returns (filled size, notional)
+fn walk(levels: &[(f64, f64)], size: f64, crosses: impl Fn(f64) -> bool) -> (f64, f64) {
+    let mut remaining = size;
+    let mut notional = 0.0;
+    for &(price, available) in levels.iter().take_while(|l| crosses(l.0)) {
+        if remaining <= 0.0 {
+            break;
+        }
+        let take = remaining.min(available);
+        notional += take * price;
+        remaining -= take;
+    }
+    (size - remaining, notional)
+}
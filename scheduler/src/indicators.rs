@@ -0,0 +1,270 @@
+use crate::prelude::*;
+
+/// Indicators over the candles of the strategy timeframe. Values are `None` until there
+/// are enough candles (at least 3).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Indicators {
+    //EMA of the closes over half the timeframe
+    pub ema_fast: Option<f64>,
+    //EMA of the closes over the whole timeframe. The seed already takes every candle, so
+    //it's the SMA of the timeframe
+    pub ema_slow: Option<f64>,
+    pub sma: Option<f64>,
+    pub rsi: Option<f32>,
+    pub vwap: Option<f64>,
+    //Average true range, % of the last close
+    pub atr: Option<f32>,
+    //Last candle volume against the previous ones, in standard deviations
+    pub vol_zscore: Option<f32>,
+}
+
+impl Indicators {
+    pub fn from_candles(candles: &[Candlestick]) -> Self {
+        let n = candles.len();
+        if n < 3 {
+            return Self::default();
+        }
+        let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+        let vols: Vec<f64> = candles.iter().map(|c| c.vol).collect();
+        let last_close = closes[n - 1];
+        Self {
+            ema_fast: ema(&closes, (n / 2).max(2)),
+            ema_slow: ema(&closes, n),
+            sma: sma(&closes, n),
+            rsi: rsi(&closes, n - 1).map(|x| x as f32),
+            vwap: vwap(candles),
+            atr: atr(candles, n - 1)
+                .filter(|_| last_close > 0.0)
+                .map(|x| (x / last_close * 100.0) as f32),
+            vol_zscore: zscore(&vols).map(|x| x as f32),
+        }
+    }
+
+    /// Fast EMA above the slow one
+    pub fn uptrend(&self) -> Option<bool> {
+        match (self.ema_fast, self.ema_slow) {
+            (Some(fast), Some(slow)) => Some(fast > slow),
+            _ => None,
+        }
+    }
+
+    /// Check the optional indicator filters of the strategy. Missing values fail set filters
+    pub fn matches(&self, strategy: &Strategy, price: f64) -> bool {
+        let min = |value: Option<f32>, threshold: Option<f32>| {
+            threshold.map_or(true, |t| value.map_or(false, |v| v >= t))
+        };
+        let max = |value: Option<f32>, threshold: Option<f32>| {
+            threshold.map_or(true, |t| value.map_or(false, |v| v <= t))
+        };
+        let required = |enabled: Option<bool>, value: Option<bool>| {
+            !enabled.unwrap_or(false) || value.unwrap_or(false)
+        };
+
+        required(strategy.ema_crossover, self.uptrend())
+            && required(strategy.above_vwap, self.vwap.map(|vwap| price > vwap))
+            && min(self.rsi, strategy.min_rsi)
+            && max(self.rsi, strategy.max_rsi)
+            && max(self.atr, strategy.max_atr)
+            && min(self.vol_zscore, strategy.min_vol_zscore)
+    }
+}
+
+pub fn sma(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() < period {
+        return None;
+    }
+    Some(values[values.len() - period..].iter().sum::<f64>() / period as f64)
+}
+
+/// Seeded with the SMA of the first `period` values, so with `period == values.len()`
+/// it's the SMA of the values
+pub fn ema(values: &[f64], period: usize) -> Option<f64> {
+    let seed = sma(&values[..period.min(values.len())], period)?;
+    let k = 2.0 / (period as f64 + 1.0);
+    Some(
+        values[period..]
+            .iter()
+            .fold(seed, |ema, value| value * k + ema * (1.0 - k)),
+    )
+}
+
+/// Average gains against average losses over the last `period` closes
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() <= period {
+        return None;
+    }
+    let (gains, losses) = closes[closes.len() - period - 1..]
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .fold((0.0, 0.0), |(gains, losses), diff| {
+            if diff > 0.0 {
+                (gains + diff, losses)
+            } else {
+                (gains, losses - diff)
+            }
+        });
+    if losses == 0.0 {
+        return Some(if gains == 0.0 { 50.0 } else { 100.0 });
+    }
+    Some(100.0 - 100.0 / (1.0 + gains / losses))
+}
+
+/// Typical price weighted by volume
+pub fn vwap(candles: &[Candlestick]) -> Option<f64> {
+    let (notional, vol) = candles.iter().fold((0.0, 0.0), |(notional, vol), c| {
+        (
+            notional + (c.high + c.low + c.close) / 3.0 * c.vol,
+            vol + c.vol,
+        )
+    });
+    if vol > 0.0 {
+        Some(notional / vol)
+    } else {
+        None
+    }
+}
+
+pub fn atr(candles: &[Candlestick], period: usize) -> Option<f64> {
+    if period == 0 || candles.len() <= period {
+        return None;
+    }
+    let ranges: Vec<f64> = candles
+        .windows(2)
+        .map(|w| {
+            let (prev, c) = (&w[0], &w[1]);
+            (c.high - c.low)
+                .max((c.high - prev.close).abs())
+                .max((c.low - prev.close).abs())
+        })
+        .collect();
+    sma(&ranges, period)
+}
+
+/// Pearson correlation of two series of the same length
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() != b.len() || a.len() < 3 {
+        return None;
+    }
+    let n = a.len() as f64;
+    let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+    let (cov, var_a, var_b) = a
+        .iter()
+        .zip(b)
+        .fold((0.0, 0.0, 0.0), |(cov, va, vb), (x, y)| {
+            let (dx, dy) = (x - mean_a, y - mean_b);
+            (cov + dx * dy, va + dx * dx, vb + dy * dy)
+        });
+    if var_a == 0.0 || var_b == 0.0 {
+        return None;
+    }
+    Some(cov / (var_a * var_b).sqrt())
+}
+
+/// Z-score of the last value against the previous ones
+pub fn zscore(values: &[f64]) -> Option<f64> {
+    let (last, previous) = values.split_last()?;
+    if previous.is_empty() {
+        return None;
+    }
+    let mean = previous.iter().sum::<f64>() / previous.len() as f64;
+    let std =
+        (previous.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / previous.len() as f64).sqrt();
+    if std > 0.0 {
+        Some((last - mean) / std)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(high: f64, low: f64, close: f64, vol: f64) -> Candlestick {
+        Candlestick {
+            high,
+            low,
+            close,
+            vol,
+            ..Candlestick::new(close, Utc::now())
+        }
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("missing value");
+        assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn moving_averages() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_close(sma(&values, 3), 4.0);
+        //Seed 2 (sma of 1, 2, 3), k = 0.5: 3 after 4, 4 after 5
+        assert_close(ema(&values, 3), 4.0);
+        assert_eq!(ema(&values, 5), sma(&values, 5));
+        assert_eq!(sma(&values, 6), None);
+        assert_eq!(ema(&values, 6), None);
+    }
+
+    #[test]
+    fn slow_ema_is_the_timeframe_sma() {
+        let candles: Vec<Candlestick> = [1.0, 3.0, 2.0, 5.0, 4.0, 6.0]
+            .iter()
+            .map(|close| candle(*close, *close, *close, 1.0))
+            .collect();
+        let indicators = Indicators::from_candles(&candles);
+        assert_eq!(indicators.ema_slow, indicators.sma);
+        assert_close(indicators.sma, 3.5);
+    }
+
+    #[test]
+    fn wilder_rsi() {
+        //Wilder's example closes, simple averages of the first 14 changes
+        let closes = [
+            44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03,
+            45.61, 46.28, 46.28,
+        ];
+        assert_close(rsi(&closes, 14), 70.464);
+        assert_close(rsi(&[1.0, 2.0, 3.0], 2), 100.0);
+        assert_close(rsi(&[1.0, 1.0, 1.0], 2), 50.0);
+        assert_eq!(rsi(&closes, 15), None);
+    }
+
+    #[test]
+    fn volume_weighted_price() {
+        //Typical prices 9.5 and 10.5
+        let candles = [
+            candle(10.0, 9.0, 9.5, 100.0),
+            candle(11.0, 10.0, 10.5, 300.0),
+        ];
+        assert_close(vwap(&candles), 10.25);
+        assert_eq!(vwap(&[candle(10.0, 9.0, 9.5, 0.0)]), None);
+    }
+
+    #[test]
+    fn average_true_range() {
+        //True ranges: 1.5 (gap up from 9.5), 0.8 (high - low)
+        let candles = [
+            candle(10.0, 9.0, 9.5, 1.0),
+            candle(11.0, 10.0, 10.5, 1.0),
+            candle(10.6, 9.8, 10.0, 1.0),
+        ];
+        assert_close(atr(&candles, 2), 1.15);
+        assert_eq!(atr(&candles, 3), None);
+    }
+
+    #[test]
+    fn volume_zscore() {
+        //Mean 2.5, population std 1.118
+        assert_close(zscore(&[1.0, 2.0, 3.0, 4.0, 10.0]), 6.708);
+        assert_eq!(zscore(&[2.0, 2.0, 5.0]), None);
+        assert_eq!(zscore(&[1.0]), None);
+    }
+
+    #[test]
+    fn pearson_correlation() {
+        assert_close(correlation(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]), 1.0);
+        assert_close(correlation(&[1.0, 2.0, 3.0], &[6.0, 4.0, 2.0]), -1.0);
+        assert_eq!(correlation(&[1.0, 2.0], &[1.0, 2.0]), None);
+    }
+}
@@ -1,9 +1,20 @@
 pub use prelude::*;
 use ws::{channel, server};
+mod alerts;
 mod app;
+mod backtest;
+mod events;
+mod exchange;
+mod indicators;
 mod models;
-mod okx;
 mod prelude;
+mod recovery;
+mod regime;
+mod risk;
+mod search;
+mod sizing;
+mod strategies;
+mod strategy;
 mod ui;
 mod utils;
 mod ws;
@@ -12,22 +23,83 @@ const REFRESH_CYCLES: u64 = 950;
 const NOTIFY_SECS: i64 = 1800;
 const UI_LOG_LINES: usize = 8;
 
-pub const BASE_URL: &str = "https://www.okx.com";
-pub const ORDERS_ENDPOINT: &str = "/api/v5/trade/order";
-pub const BALANCE_ENDPOINT: &str = "/api/v5/account/balance";
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let mut cfg: AppConfig = AppConfig::load()?;
+
+    //scheduler backtest --from <ts> --to <ts> [--strategy <file>]
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("backtest") {
+        backtest::Backtest::from_args(&args[2..])?.run(cfg).await?;
+        return Ok(());
+    }
+
+    //scheduler search --from <ts> --to <ts> [--runs <n>] | --output <file>
+    if args.get(1).map(String::as_str) == Some("search") {
+        search::StrategySearch::from_args(&args[2..])?
+            .run(cfg)
+            .await?;
+        return Ok(());
+    }
+
     //hash and save the strategy to the DB
+    cfg.strategy.validate()?;
     cfg.strategy.hash = cfg.strategy.get_hash();
 
     let mut app = App::init(&cfg).await?;
 
+    //Don't resume after a risk halt until it's acknowledged with: scheduler ack
+    if let Some(mut halt) = Halt::pending(&app.db_session, &app.exchange.name).await? {
+        if args.get(1).map(String::as_str) == Some("ack") {
+            halt.acknowledge(&app.db_session).await?;
+            log::info!("Acknowledged: {}", halt.to_string());
+            return Ok(());
+        }
+        return Err(format!(
+            "{}. Review it and run `scheduler ack` to resume trading",
+            halt.to_string()
+        )
+        .into());
+    }
+    if args.get(1).map(String::as_str) == Some("ack") {
+        log::info!("No pending halts");
+        return Ok(());
+    }
+
     //setup account balance and spendable per token
     let mut account = Account::new().set_balance(cfg.account.balance, cfg.account.spendable);
     account.authentication = cfg.exchange.clone().unwrap_or_default().authentication;
 
+    //Resume the portfolio saved by the previous run, unless started with --fresh
+    if !args.iter().any(|a| a == "--fresh") {
+        if let Some((snapshot, ts)) = Snapshot::load(&app.db_session, &app.exchange.name).await? {
+            account = snapshot.restore(&mut app, account).await?;
+            app.logs.push(format!(
+                "Resumed {} portfolio tokens from the state saved at {}",
+                account.portfolio.len(),
+                Utc.timestamp_millis_opt(ts)
+                    .single()
+                    .unwrap_or_default()
+                    .format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+    }
+
+    //Refuse strategies that already lost money unless started with --force.
+    //Skipped when resuming open positions, they still have to be managed
+    let open_positions = account
+        .portfolio
+        .iter()
+        .any(|t| t.status != token::Status::Exited);
+    if !open_positions {
+        History::check(
+            &app.db_session,
+            &cfg.strategy,
+            args.iter().any(|a| a == "--force"),
+        )
+        .await?;
+    }
+
     app.set_cooldown(cfg.strategy.cooldown);
     if cfg.ui.enable {
         app.term.hide_cursor()?;
@@ -50,54 +122,58 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    let mut strategy_watcher = cfg.strategy_file.as_deref().map(StrategyWatcher::new);
+
     let mut quickstart_completed = false;
     loop {
         if cfg.ui.enable {
             app.term.move_cursor_to(0, 0)?;
         }
+
+        //Hot-reload the strategy file. Open positions keep the strategy they were bought with
+        if let Some(watcher) = strategy_watcher.as_mut() {
+            match watcher.reload() {
+                Ok(Some(strategy)) if strategy.hash != cfg.strategy.hash => {
+                    app.save_strategy(&strategy).await?;
+                    app.set_cooldown(strategy.cooldown);
+                    for token in strategy.deny_list.clone().unwrap_or_default() {
+                        if !app.deny_list.contains(&token) {
+                            app.deny_list.push(token);
+                        }
+                    }
+                    app.logs.push(format!(
+                        "Strategy reloaded from {}: {:.7} -> {:.7}",
+                        watcher.path, cfg.strategy.hash, strategy.hash
+                    ));
+                    cfg.strategy = strategy;
+                },
+                Ok(_) => (),
+                Err(e) => app.logs.push(format!(
+                    "Ignoring strategy changes in {}: {}",
+                    watcher.path, e
+                )),
+            }
+        }
         app.time.utc = Utc::now();
         let unix_timestamp = app.time.utc.timestamp();
         app.time.now = time::Instant::now();
 
-        //Retrieve and process top tokens
-        app.fetch_tokens(cfg.strategy.timeframe).await?;
-        app.tokens = app
-            .update_candles(cfg.strategy.timeframe, app.tokens.clone())
-            .await?;
-
-        app.filter_invalid(&cfg.strategy, account.balance.spendable);
-        app.clean_top(cfg.strategy.top).get_tickers().await?;
-
-        //update timers in portfolio tokens
-        account = app.buy_tokens(account, &cfg.strategy).await?;
-
-        //update portfolio and tracked tokens
-        app.update_cooldowns(&account.portfolio);
-
-        account.portfolio = app.update_timeouts(account.portfolio, &cfg.strategy);
-        account.portfolio = app
-            .update_candles(cfg.strategy.timeframe, account.portfolio)
-            .await?;
+        account = app.cycle(account, &cfg.strategy).await?;
+        account.clean_portfolio();
 
-        //update portfolio
-        for token in account.portfolio.iter_mut() {
-            token
-                .update_reports(cfg.strategy.timeout)
-                .update_orders(app.exchange.enable_trading, &app.exchange.authentication)
-                .await?
-                .tag_invalid(&app.tokens, &cfg.strategy)?;
+        //Persist the portfolio so it can be resumed after a crash
+        if let Err(e) = Snapshot::new(&app, &account)
+            .save(
+                &app.db_session,
+                &app.exchange.name,
+                app.time.utc.timestamp_millis(),
+            )
+            .await
+        {
+            app.logs
+                .push(format!("Unable to save the portfolio state: {}", e));
         }
 
-        account.balance.set_current(0.0);
-        account
-            .calculate_balance(&mut app)
-            .await?
-            .calculate_earnings();
-
-        //account = app.tag_invalid_tokens(account, &cfg.strategy)?;
-        account = app.sell_tokens(account, &cfg.strategy).await?;
-        account.clean_portfolio();
-
         // Websocket
         // Only send tokens that are actively trading
         let trading_tokens: Vec<Token> = account
@@ -117,6 +193,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             fee_spend: account.fee_spend,
             earnings: account.earnings,
             change: account.change,
+            regime: app
+                .regime
+                .is_enabled()
+                .then(|| app.regime.regime.to_string()),
             ts: app.time.utc,
         };
 
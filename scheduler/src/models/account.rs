@@ -52,6 +52,7 @@ impl Account {
         let mut open_order_value = 0.0;
         let mut token_balances = 0.0;
         for t in self.portfolio.iter_mut() {
+            let spendable = t.allocated(self.balance.spendable);
             if let Some(orders) = t.orders.as_mut() {
                 for order in orders.iter_mut() {
                     if order.state == order.prev_state {
@@ -77,28 +78,80 @@ impl Account {
                     };
 
                     //calculate fees based on the order
-                    let usdt_taker_fee =
-                        calculate_fees(self.balance.spendable, app.exchange.taker_fee);
+                    let usdt_taker_fee = calculate_fees(spendable, app.exchange.taker_fee);
                     let usdt_order_amount = (size * price) - usdt_taker_fee;
                     let token_balance_after_fees =
                         size - calculate_fees(size, app.exchange.taker_fee); //+ 0.0001);
+
+                    //Paper fills carry their own price, size and fee
+                    let simulated = !app.exchange.enable_trading && order.fill_sz > 0.0;
+                    let (fill_price, fill_size) = order.filled().unwrap_or((price, size));
                     match order.state {
                         OrderState::Live => match order.side {
                             Side::Buy => {
-                                self.balance.available -= self.balance.spendable;
-                                open_order_value += self.balance.spendable;
+                                self.balance.available -= spendable;
+                                open_order_value += spendable;
                             },
                             Side::Sell => {
                                 t.balance.available -= size;
                                 open_order_value += usdt_order_amount;
                             },
                         },
+                        //Partial fills before the cancellation stay in the token balance
                         OrderState::Cancelled => match order.side {
                             Side::Buy => {
-                                self.balance.available += self.balance.spendable;
+                                let received = if app.exchange.enable_trading {
+                                    Account::get_balance(
+                                        app.broker.as_ref(),
+                                        &t.instid.replace("-USDT", ""),
+                                        &app.exchange.authentication,
+                                    )
+                                    .await
+                                    .unwrap_or_default()
+                                } else if order.fill_sz > 0.0 {
+                                    order.fill_sz - order.fee / order.fill_px
+                                } else {
+                                    0.0
+                                };
+                                let spent = if order.fill_sz > 0.0 {
+                                    order.fill_sz * order.fill_px
+                                } else {
+                                    received * price
+                                };
+                                self.balance.available += spendable - spent;
+                                if received * price > 1.0 {
+                                    t.balance.available = received;
+                                    t.balance.current = received;
+                                    t.status = token::Status::Trading;
+                                    self.trades += 1;
+                                    self.fee_spend += order.fee;
+                                }
                             },
                             Side::Sell => {
-                                t.balance.available += size;
+                                let sold = if app.exchange.enable_trading {
+                                    let left = Account::get_balance(
+                                        app.broker.as_ref(),
+                                        &t.instid.replace("-USDT", ""),
+                                        &app.exchange.authentication,
+                                    )
+                                    .await
+                                    .unwrap_or(t.balance.current);
+                                    (t.balance.current - left).max(0.0)
+                                } else {
+                                    order.fill_sz
+                                };
+                                t.balance.current -= sold;
+                                t.balance.available += size - sold;
+                                if sold > 0.0 {
+                                    let fee = if order.fee > 0.0 {
+                                        order.fee
+                                    } else {
+                                        calculate_fees(sold * fill_price, app.exchange.taker_fee)
+                                    };
+                                    self.balance.available += sold * fill_price - fee;
+                                    self.trades += 1;
+                                    self.fee_spend += fee;
+                                }
                             },
                         },
                         OrderState::Failed => match order.side {
@@ -106,6 +159,7 @@ impl Account {
                             Side::Sell => {
                                 if app.exchange.enable_trading {
                                     t.balance.available = Account::get_balance(
+                                        app.broker.as_ref(),
                                         &t.instid.replace("-USDT", ""),
                                         &app.exchange.authentication,
                                     )
@@ -120,6 +174,7 @@ impl Account {
                                     if app.exchange.enable_trading {
                                         log::info!("Retrieving balance from exchange");
                                         let balance = Account::get_balance(
+                                            app.broker.as_ref(),
                                             &t.instid.replace("-USDT", ""),
                                             &app.exchange.authentication,
                                         )
@@ -128,6 +183,14 @@ impl Account {
                                         //Add the balance to the token. this will be balance with fees discounted.
                                         t.balance.available = balance;
                                         t.balance.current = t.balance.available;
+                                    } else if simulated {
+                                        //Buy fees are charged on the received token
+                                        let received = fill_size - order.fee / fill_price;
+                                        t.balance.available = received;
+                                        t.balance.current = received;
+                                        //Return what wasn't spent (partial fill or better price)
+                                        self.balance.available +=
+                                            spendable - fill_size * fill_price;
                                     } else {
                                         //Calculate fees locally
                                         t.balance.available = token_balance_after_fees;
@@ -135,14 +198,45 @@ impl Account {
                                     }
                                 },
                                 Side::Sell => {
-                                    t.balance.current -= size;
-                                    self.balance.available += token_balance_after_fees * price;
+                                    if simulated {
+                                        t.balance.current -= fill_size;
+                                        t.balance.available += size - fill_size;
+                                        self.balance.available +=
+                                            fill_size * fill_price - order.fee;
+                                    } else {
+                                        t.balance.current -= size;
+                                        self.balance.available += token_balance_after_fees * price;
+                                    }
                                     app.logs.push(t.report.to_string());
                                 },
                             }
                             self.trades += 1;
-                            self.fee_spend += usdt_taker_fee;
-                            t.buy_ts = Duration::milliseconds(Utc::now().timestamp_millis());
+                            self.fee_spend += if simulated { order.fee } else { usdt_taker_fee };
+                            t.buy_ts = Duration::milliseconds(app.time.utc.timestamp_millis());
+                            order.save(&app.db_session).await?;
+                            let ts = app.time.utc;
+                            app.events.push(
+                                Event::OrderFilled {
+                                    order: order.clone(),
+                                    fill_px: order.fill_px,
+                                    fill_sz: order.fill_sz,
+                                    fee: order.fee,
+                                },
+                                &order.strategy,
+                                ts,
+                            );
+                            if order.side == Side::Buy {
+                                app.events.push(
+                                    Event::PositionOpened {
+                                        instid: t.instid.clone(),
+                                        round_id: t.report.round_id,
+                                        price: fill_price,
+                                        size: t.balance.current,
+                                    },
+                                    &order.strategy,
+                                    ts,
+                                );
+                            }
                         },
                         _ => {},
                     };
@@ -175,7 +269,9 @@ impl Account {
         self.portfolio.retain(|t| {
             let waiting = t.status == token::Status::Waiting;
             let live_orders = if let Some(orders) = t.orders.clone() {
-                orders.iter().any(|o| o.state == OrderState::Live)
+                orders
+                    .iter()
+                    .any(|o| matches!(o.state, OrderState::Live | OrderState::PartiallyFilled))
             } else {
                 true
             };
@@ -194,52 +290,39 @@ impl Account {
         self
     }
 
-    pub fn add_token(&mut self, token: &Token, strategy: &Strategy) -> &Self {
-        if self.balance.available >= self.balance.spendable
-            && self.portfolio.len() < strategy.portfolio_size as usize
+    /// Sell everything that is still held. Used when trading is halted
+    pub fn liquidate(&mut self) -> &mut Self {
+        for t in self.portfolio.iter_mut() {
+            if t.status == token::Status::Trading {
+                t.exit_reason = Some(ExitReason::Halted);
+                t.status = token::Status::Selling;
+                t.report.reason = ExitReason::Halted.to_string();
+            }
+        }
+        self
+    }
+
+    /// Add the token to the portfolio to be bought for `size` USDT. False when there's no room
+    pub fn add_token(&mut self, token: &Token, strategy: &Strategy, size: f64) -> bool {
+        if self.balance.available >= size && self.portfolio.len() < strategy.portfolio_size as usize
         {
             let mut t = Token::new(&token.instid);
             t.price = token.price;
             t.status = token::Status::Buying;
             t.candlesticks = token.candlesticks.clone();
+            t.balance.spendable = size;
             self.portfolio.push(t);
+            return true;
         }
-        self
+        false
     }
 
-    pub async fn get_balance(token_id: &str, auth: &Authentication) -> Result<f64> {
-        let query = &format!("?ccy={token_id}", token_id = token_id);
-        let signed = auth.sign(
-            "GET",
-            BALANCE_ENDPOINT,
-            OffsetDateTime::now_utc(),
-            false,
-            query,
-        )?;
-        log::info!("Retrieving balance of: {}", token_id);
-        let res = reqwest::Client::new()
-            .get(format!("{BASE_URL}{BALANCE_ENDPOINT}{query}"))
-            .header("OK-ACCESS-KEY", &auth.access_key)
-            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
-            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
-            .header("OK-ACCESS-SIGN", signed.signature.as_str())
-            .send()
-            .await?
-            .json::<OkxAccountBalanceResponse>()
-            .await?;
-
-        log::info!("Response: {}", serde_json::to_string_pretty(&res)?);
-
-        let balance =
-            if let Some(balance) = res.data.get(0).and_then(|balance| balance.details.get(0)) {
-                balance
-                    .avail_bal
-                    .parse::<f64>()
-                    .map_err(|_| anyhow::anyhow!("Failed to parse balance"))?
-            } else {
-                return Err(anyhow::anyhow!("Failed to retrieve balance details"));
-            };
-        Ok(balance.to_owned())
+    pub async fn get_balance(
+        broker: &dyn Broker,
+        token_id: &str,
+        auth: &Authentication,
+    ) -> Result<f64> {
+        broker.balance(token_id, auth).await
     }
 }
 
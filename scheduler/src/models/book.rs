@@ -0,0 +1,39 @@
+use scylla::{cql_to_rust::FromCqlVal, macros::FromUserType};
+
+use crate::prelude::*;
+
+//Matches the order_entry UDT in okx.books
+#[derive(FromUserType, Debug, Clone, Default)]
+pub struct OrderEntry {
+    pub price: f64,
+    pub quantity: f64,
+    pub deprecated: Option<i32>,
+    pub num_orders: Option<i32>,
+}
+
+/// Top of book summary from the latest stored snapshot
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BookDepth {
+    //Best ask vs best bid, % of the mid price
+    pub spread: f32,
+    //Quote volume resting on the stored levels of each side
+    pub bid_depth: f64,
+    pub ask_depth: f64,
+}
+
+impl BookDepth {
+    pub fn new(asks: &[OrderEntry], bids: &[OrderEntry]) -> Option<Self> {
+        let best_ask = asks.first()?.price;
+        let best_bid = bids.first()?.price;
+        let mid = (best_ask + best_bid) / 2.0;
+        if mid <= 0.0 {
+            return None;
+        }
+        let notional = |side: &[OrderEntry]| side.iter().map(|e| e.price * e.quantity).sum();
+        Some(Self {
+            spread: (((best_ask - best_bid) / mid) * 100.0) as f32,
+            bid_depth: notional(bids),
+            ask_depth: notional(asks),
+        })
+    }
+}
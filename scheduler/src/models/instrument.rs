@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Trading rules of a spot instrument
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Instrument {
+    pub instid: String,
+    //Price increment
+    pub tick_sz: f64,
+    //Size increment
+    pub lot_sz: f64,
+    //Minimum order size
+    pub min_sz: f64,
+}
+
+impl Instrument {
+    /// Round the price to the nearest tick
+    pub fn round_price(&self, px: f64) -> String {
+        if self.tick_sz <= 0.0 {
+            return px.to_string();
+        }
+        let px = (px / self.tick_sz).round() * self.tick_sz;
+        format!("{:.*}", decimals(self.tick_sz), px)
+    }
+
+    /// Round the size down to the lot size, never ordering more than held
+    pub fn round_size(&self, sz: f64) -> f64 {
+        if self.lot_sz <= 0.0 {
+            return sz;
+        }
+        //Tolerate float errors right below a lot
+        (sz / self.lot_sz + 1e-9).floor() * self.lot_sz
+    }
+
+    pub fn format_size(&self, sz: f64) -> String {
+        if self.lot_sz <= 0.0 {
+            return sz.to_string();
+        }
+        format!("{:.*}", decimals(self.lot_sz), self.round_size(sz))
+    }
+
+    pub fn is_valid_size(&self, sz: f64) -> bool {
+        sz > 0.0 && sz >= self.min_sz
+    }
+}
+
+/// Decimals needed to print a step (0.001 -> 3)
+fn decimals(step: f64) -> usize {
+    (-step.log10()).ceil().max(0.0) as usize
+}
+
+/// Instrument rules by instid. Loaded from the exchange on startup and cached in
+/// `okx.instruments` for offline runs (backtests, exchange unreachable)
+#[derive(Debug, Clone, Default)]
+pub struct Instruments {
+    rules: HashMap<String, Instrument>,
+}
+
+impl Instruments {
+    pub async fn load(db_session: &Session, broker: &dyn Broker) -> Result<Self> {
+        match broker.instruments().await {
+            Ok(list) if !list.is_empty() => {
+                for instrument in list.iter() {
+                    Self::save(db_session, broker.name(), instrument).await?;
+                }
+                log::info!("Loaded {} instruments from {}", list.len(), broker.name());
+                Ok(Self::from(list))
+            },
+            res => {
+                if let Err(e) = res {
+                    log::warn!("Unable to fetch instruments, using the cached ones: {}", e);
+                }
+                Self::cached(db_session, broker.name()).await
+            },
+        }
+    }
+
+    async fn save(db_session: &Session, exchange: &str, i: &Instrument) -> Result<QueryResult> {
+        Ok(db_session
+            .query(
+                "INSERT INTO okx.instruments (exchange, instid, tick_sz, lot_sz, min_sz) VALUES (?, ?, ?, ?, ?)",
+                (exchange, &i.instid, i.tick_sz, i.lot_sz, i.min_sz),
+            )
+            .await?)
+    }
+
+    async fn cached(db_session: &Session, exchange: &str) -> Result<Self> {
+        let query =
+            "SELECT instid, tick_sz, lot_sz, min_sz FROM okx.instruments WHERE exchange = ?";
+        let mut list = Vec::new();
+        if let Some(rows) = db_session.query(query, (exchange,)).await?.rows {
+            for row in rows.into_typed::<(String, f64, f64, f64)>() {
+                let (instid, tick_sz, lot_sz, min_sz) = row?;
+                list.push(Instrument {
+                    instid,
+                    tick_sz,
+                    lot_sz,
+                    min_sz,
+                });
+            }
+        }
+        if list.is_empty() {
+            log::warn!("No cached instruments, orders won't be rounded");
+        }
+        Ok(Self::from(list))
+    }
+
+    pub fn get(&self, instid: &str) -> Option<&Instrument> {
+        self.rules.get(instid)
+    }
+}
+
+impl From<Vec<Instrument>> for Instruments {
+    fn from(list: Vec<Instrument>) -> Self {
+        Self {
+            rules: list.into_iter().map(|i| (i.instid.clone(), i)).collect(),
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod account;
+pub mod book;
+pub mod instrument;
 pub mod report;
 pub mod token;
 pub mod trade;
@@ -39,12 +39,12 @@ impl Default for Report {
     }
 }
 impl Report {
-    pub fn new(round_id: u64, strategy_hash: &str, t: &Token) -> Self {
+    pub fn new(round_id: u64, strategy_hash: &str, t: &Token, ts: DateTime<Utc>) -> Self {
         Self {
             round_id,
             reason: "None".to_string(),
             instid: t.instid.clone(),
-            ts: Utc::now().timestamp_millis().to_string(),
+            ts: ts.timestamp_millis().to_string(),
             buy_price: t.price,
             strategy: strategy_hash.to_string(),
             change: t.change,
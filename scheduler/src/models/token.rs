@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{indicators, prelude::*, strategies};
 
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
 pub enum Status {
@@ -17,6 +17,10 @@ impl Status {
                 Side::Buy => Status::Trading,
                 Side::Sell => Status::Exited,
             },
+            //Cancelled orders keep what they filled before
+            OrderState::Cancelled if order.side == Side::Buy && order.fill_sz > 0.0 => {
+                Status::Trading
+            },
             OrderState::Cancelled | OrderState::Failed => match order.side {
                 Side::Buy => Status::Waiting,
                 Side::Sell => Status::Trading,
@@ -51,13 +55,26 @@ pub struct Token {
     pub change24h: f32,
     pub range: f32,
     pub range24h: f32,
+    #[serde(default)]
+    pub book: BookDepth,
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     pub cooldown: Duration,
     pub candlesticks: Vec<Candlestick>,
+    #[serde(default)]
+    pub indicators: Indicators,
+    //Buy minus sell volume of the current minute trades, % of the traded volume
+    #[serde(default)]
+    pub imbalance: Option<f32>,
     pub config: Config,
     pub orders: Option<Vec<trade::Order>>,
     pub exit_reason: Option<trade::ExitReason>,
     pub report: Report,
+    //Hash of the strategy the token was bought with
+    #[serde(default)]
+    pub strategy: String,
+    //Take-profit/stop-loss kept on the exchange (native_exits)
+    #[serde(default)]
+    pub exit_id: Option<String>,
 }
 
 #[serde_with::serde_as]
@@ -75,18 +92,13 @@ pub struct Candlestick {
     pub vol: f64,
 }
 
-impl Default for Candlestick {
-    fn default() -> Self {
-        Candlestick::new(0.0)
-    }
-}
 impl Candlestick {
-    pub fn new(open: f64) -> Self {
+    /// Flat candle for the minute of `now` (simulated time in backtests)
+    pub fn new(open: f64, now: DateTime<Utc>) -> Self {
         Self {
             instid: String::new(),
             ts: Duration::milliseconds(
-                Utc::now()
-                    .with_second(0)
+                now.with_second(0)
                     .unwrap()
                     .with_nanosecond(0)
                     .unwrap()
@@ -101,7 +113,23 @@ impl Candlestick {
             vol: 0.0,
         }
     }
-    pub fn from_tickers(instid: &str, tickers: &[(f64, f64, Duration)]) -> Option<Candlestick> {
+    /// Candle from the (px, sz, side, ts) trades of the minute
+    pub fn from_trades(
+        instid: &str,
+        trades: &[(f64, f64, String, Duration)],
+        now: DateTime<Utc>,
+    ) -> Option<Candlestick> {
+        let fills: Vec<(f64, f64, Duration)> = trades
+            .iter()
+            .map(|(px, sz, _, ts)| (*px, *sz, *ts))
+            .collect();
+        Candlestick::from_tickers(instid, &fills, now)
+    }
+    pub fn from_tickers(
+        instid: &str,
+        tickers: &[(f64, f64, Duration)],
+        now: DateTime<Utc>,
+    ) -> Option<Candlestick> {
         if tickers.is_empty() {
             return None;
         }
@@ -124,7 +152,7 @@ impl Candlestick {
         let datetime =
             DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp_opt(0, 0)?, Utc) + ts;
         let time = if datetime.timestamp_millis() == 0 {
-            Utc::now()
+            now
         } else {
             datetime
         };
@@ -187,14 +215,19 @@ impl Token {
             vol: 0.0,
             vol24h: 0.0,
             range24h: 0.0,
+            book: BookDepth::default(),
             timeout: Duration::seconds(0),
             cooldown: Duration::seconds(0),
             config: Config::default(),
             exit_reason: None,
             change: 0.00,
             candlesticks: Vec::new(),
+            indicators: Indicators::default(),
+            imbalance: None,
             orders: None,
             report: Report::default(),
+            strategy: String::new(),
+            exit_id: None,
             status: token::Status::Waiting,
         }
     }
@@ -215,26 +248,76 @@ impl Token {
         }
     }
 
+    /// Aggressor flow from the (px, sz, side, ts) trades of the current minute
+    pub fn set_imbalance(&mut self, trades: &[(f64, f64, String, Duration)]) {
+        let (buys, sells) = trades
+            .iter()
+            .fold((0.0, 0.0), |(buys, sells), (px, sz, side, _)| {
+                match side.as_str() {
+                    "buy" => (buys + px * sz, sells),
+                    _ => (buys, sells + px * sz),
+                }
+            });
+        self.imbalance = if buys + sells > 0.0 {
+            Some(((buys - sells) / (buys + sells) * 100.0) as f32)
+        } else {
+            None
+        };
+    }
+
     pub async fn buy(
         &mut self,
+        broker: &dyn Broker,
         trade_enabled: bool,
         auth: Authentication,
         strategy: &Strategy,
+        instrument: Option<&Instrument>,
     ) -> Result<&Self> {
         self.buy_price = self.price;
+        let size = instrument.map_or(self.balance.start, |i| i.round_size(self.balance.start));
         let mut order = trade::Order::new(
             &self.instid,
-            self.buy_price.to_string(),
-            self.balance.start.to_string(),
+            instrument.map_or_else(
+                || self.buy_price.to_string(),
+                |i| i.round_price(self.buy_price),
+            ),
+            instrument.map_or_else(|| size.to_string(), |i| i.format_size(size)),
             Side::Buy,
             &strategy.order_type,
             &strategy.hash,
         );
-        order.publish(trade_enabled, &auth).await?;
+        match instrument {
+            Some(i) if !i.is_valid_size(size) => {
+                log::warn!(
+                    "[{}] Buy size {} is under the minimum of {}",
+                    self.instid,
+                    size,
+                    i.min_sz
+                );
+                order.state = OrderState::Failed;
+            },
+            _ => order.publish(broker, trade_enabled, &auth).await?,
+        }
         self.orders.get_or_insert_with(Vec::new).push(order);
 
         Ok(self)
     }
+    /// USDT set aside to buy the token. Tokens added before sizing use the account spendable
+    pub fn allocated(&self, spendable: f64) -> f64 {
+        if self.balance.spendable > 0.0 {
+            self.balance.spendable
+        } else {
+            spendable
+        }
+    }
+    /// Date the last order with the given clock, backtests run on their own time
+    pub fn stamp_last_order(&mut self, now: DateTime<Utc>) -> &mut Self {
+        if let Some(order) = self.orders.as_mut().and_then(|orders| orders.last_mut()) {
+            order.ts = now.timestamp_millis().to_string();
+        }
+        self
+    }
+
     pub fn tag_invalid(
         &mut self,
         //mut account: Account,
@@ -243,7 +326,7 @@ impl Token {
     ) -> Result<&mut Self> {
         let found = tokens.iter().any(|t| self.instid == t.instid);
         if self.status == token::Status::Trading {
-            self.exit_reason = self.get_exit_reason(strategy, found);
+            self.exit_reason = strategies::from_strategy(strategy)?.exit(self, strategy, found);
         }
         if let Some(reason) = &self.exit_reason {
             self.status = token::Status::Selling;
@@ -254,23 +337,18 @@ impl Token {
 
     pub async fn sell(
         &mut self,
+        broker: &dyn Broker,
         trade_enabled: bool,
         auth: Authentication,
         strategy: &Strategy,
+        instrument: Option<&Instrument>,
     ) -> Result<&Self> {
         let sell_balance = if trade_enabled {
-            Account::get_balance(&self.instid.replace("-USDT", ""), &auth)
+            Account::get_balance(broker, &self.instid.replace("-USDT", ""), &auth)
                 .await
                 .unwrap_or(self.balance.available)
         } else {
             self.balance.available
-            /*
-            match self.balance.available {
-                x if x > 1_000_000.0 => ((x / 1_000_000.0).floor() * 1_000_000.0) - 1.0,
-                x if x > 100_000.0 => (x / 100_000.0).floor() * 100_000.0,
-                x if x > 1_000.0 => (x / 1_000.0).floor() * 1_000.0,
-                _ => self.balance.available,
-            }*/
         };
 
         //Count sell atempts and sell to market_price if above x
@@ -288,21 +366,35 @@ impl Token {
             _ => "market",
         };
 
+        let size = instrument.map_or(sell_balance, |i| i.round_size(sell_balance));
         let mut order = trade::Order::new(
             &self.instid,
-            self.price.to_string(),
-            sell_balance.to_string(),
+            instrument.map_or_else(|| self.price.to_string(), |i| i.round_price(self.price)),
+            instrument.map_or_else(|| size.to_string(), |i| i.format_size(size)),
             Side::Sell,
             ord_type,
             &strategy.hash,
         );
 
-        order.publish(trade_enabled, &auth).await?;
+        match instrument {
+            //What is left can't be sold, leave it as dust
+            Some(i) if !i.is_valid_size(size) => {
+                log::warn!(
+                    "[{}] Sell size {} is under the minimum of {}, leaving it as dust",
+                    self.instid,
+                    sell_balance,
+                    i.min_sz
+                );
+                order.state = OrderState::Failed;
+                self.status = Status::Exited;
+            },
+            _ => order.publish(broker, trade_enabled, &auth).await?,
+        }
 
         if order
             .response
             .as_ref()
-            .map_or(false, |response| response.code.parse::<i64>().unwrap() != 0)
+            .map_or(false, |response| !response.is_success())
         {
             order.state = OrderState::Failed;
         }
@@ -356,6 +448,29 @@ impl Token {
             return Some(ExitReason::Cashout);
         }
 
+        //Only gains are trailed, losses are left to the stoploss
+        let highest = self.report.highest;
+        if let Some(trailing) = strategy.trailing_stop {
+            if highest > 0.0 && highest - self.change >= trailing {
+                return Some(ExitReason::TrailingStop);
+            }
+        }
+
+        if let Some(threshold) = strategy.break_even {
+            if highest >= threshold && self.change <= 0.0 {
+                return Some(ExitReason::BreakEven);
+            }
+        }
+
+        if let Some(min_cashout) = strategy.min_cashout {
+            let elapsed = 1.0 - self.timeout.num_seconds() as f32 / strategy.timeout as f32;
+            let target =
+                strategy.cashout - (strategy.cashout - min_cashout) * elapsed.clamp(0.0, 1.0);
+            if self.change >= target {
+                return Some(ExitReason::DecayedCashout);
+            }
+        }
+
         if self.change >= sell_floor && self.timeout < timeout_threshold && !token_found {
             return Some(ExitReason::FloorReached);
         }
@@ -424,7 +539,46 @@ impl Token {
         self
     }
 
+    /// Correlation of the candle changes of both tokens over the minutes they share
+    pub fn correlation(&self, other: &Token) -> Option<f32> {
+        let (a, b): (Vec<f64>, Vec<f64>) = self
+            .candlesticks
+            .iter()
+            .filter_map(|c| {
+                other
+                    .candlesticks
+                    .iter()
+                    .find(|o| o.ts.num_minutes() == c.ts.num_minutes())
+                    .map(|o| (c.change as f64, o.change as f64))
+            })
+            .unzip();
+        indicators::correlation(&a, &b).map(|x| x as f32)
+    }
+
+    /// Highest correlation with the other held tokens
+    pub fn max_correlation(&self, portfolio: &[Token]) -> Option<f32> {
+        portfolio
+            .iter()
+            .filter(|t| t.instid != self.instid && t.status != Status::Exited)
+            .filter_map(|t| self.correlation(t))
+            .reduce(f32::max)
+    }
+
+    /// Momentum entry: steady positive change over the timeframe
     pub fn is_valid(&self, deny_list: &[String], strategy: &Strategy, spendable: f64) -> bool {
+        let cchange = self.candlesticks.iter().filter(|x| x.change > 0.0).count();
+        let last_change = self.candlesticks.last().map_or(0.0, |c| c.change);
+
+        self.is_tradable(deny_list, strategy, spendable)
+            // At least half of the candles have some change
+            && cchange >= strategy.timeframe as usize / 2
+            && self.change >= strategy.min_change
+            && (self.std_deviation >= strategy.min_deviation && self.std_deviation <= strategy.max_deviation)
+            && last_change > strategy.min_change_last_candle
+    }
+
+    /// Checks shared by every strategy kind: deny list, candle data, volume and indicator filters
+    pub fn is_tradable(&self, deny_list: &[String], strategy: &Strategy, spendable: f64) -> bool {
         let denied = deny_list
             .iter()
             .any(|i| format!("{}-USDT", i) == self.instid);
@@ -434,24 +588,16 @@ impl Token {
             .iter()
             .filter(|x| x.vol > spendable)
             .count();
-
-        let cchange = self.candlesticks.iter().filter(|x| x.change > 0.0).count();
-
-        let blank_candle = Candlestick::new(self.price);
-        let last_candle = self.candlesticks.last().unwrap_or(&blank_candle);
+        let last_vol = self.candlesticks.last().map_or(0.0, |c| c.vol);
 
         !denied
             // No missing candles in our data
             && self.candlesticks.len() >= strategy.timeframe as usize
             // At least half of the candles should have higher volume than our spendable
             && pcc >= strategy.timeframe as usize / 2
-            // At least half of the candles have some change
-            && cchange >= strategy.timeframe as usize / 2
-            && self.change >= strategy.min_change
-            && (self.std_deviation >= strategy.min_deviation && self.std_deviation <= strategy.max_deviation)
-            && last_candle.vol >= spendable
-            && last_candle.change > strategy.min_change_last_candle
+            && last_vol >= spendable
             && self.vol > strategy.min_vol.unwrap()
+            && self.indicators.matches(strategy, self.price)
     }
 
     pub fn sum_candles(&mut self) -> &mut Self {
@@ -477,6 +623,7 @@ impl Token {
             .map(|x| x.change)
             .collect();
         self.std_deviation = std_deviation(&changes).unwrap_or(0.0);
+        self.indicators = Indicators::from_candles(&self.candlesticks);
         self
     }
 
@@ -497,12 +644,16 @@ impl Token {
 
     pub async fn update_orders(
         &mut self,
+        broker: &dyn Broker,
         enable_trading: bool,
         auth: &Authentication,
+        simulator: &FillSimulator,
+        quote: Option<&Quote>,
+        instrument: Option<&Instrument>,
     ) -> Result<&mut Self> {
         if let Some(orders) = &mut self.orders {
             for order in orders.iter_mut().filter(|o| {
-                o.state == OrderState::Live
+                matches!(o.state, OrderState::Live | OrderState::PartiallyFilled)
                     && o.prev_state != OrderState::Created
                     && o.state != OrderState::Filled
             }) {
@@ -513,23 +664,76 @@ impl Token {
                 );
                 if enable_trading {
                     log::info!("[{}] Retrieving order state form exchange", self.instid);
-                    let got_state = order.get_state(auth).await?;
+                    let got_state = order.get_state(broker, auth).await?;
                     if order.state != got_state {
                         order.state = got_state.clone();
                     }
+                    if let Some((price, size, fee)) = broker.fill(order) {
+                        order.fill_px = price;
+                        order.fill_sz = size;
+                        order.fee = fee;
+                    }
                 } else {
-                    use rand::{thread_rng, Rng};
-                    let mut rng = thread_rng();
-                    let random_state = if rng.gen_bool(1.0 / 6.0) {
-                        OrderState::Filled
-                    } else {
-                        OrderState::Cancelled
-                    };
-
-                    order.state = random_state;
-                    order.id = order.cl_ord_id.clone();
+                    simulator.fill(order, quote, instrument);
+                    if order.side == Side::Buy && order.fill_sz > 0.0 {
+                        self.buy_price = order.fill_px;
+                    }
                 };
                 self.status = Status::from_order(order);
+                //Keep selling what a partial paper fill left behind
+                let partial = order.fill_sz > 0.0
+                    && order.sz.parse::<f64>().map_or(false, |sz| order.fill_sz < sz);
+                if order.side == Side::Sell && partial {
+                    self.status = Status::Trading;
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Deal with orders still open after `order_ttl`: re-price them once when `reprice` is
+    /// enabled, cancel them otherwise. Whatever got filled stays in the token balance.
+    pub async fn expire_orders(
+        &mut self,
+        broker: &dyn Broker,
+        exchange: &Exchange,
+        now: DateTime<Utc>,
+    ) -> Result<&mut Self> {
+        let ttl = Duration::seconds(exchange.order_ttl as i64);
+        let reprice = exchange.reprice.unwrap_or(false);
+        let auth = &exchange.authentication;
+        if let Some(orders) = &mut self.orders {
+            for order in orders.iter_mut().filter(|o| {
+                matches!(o.state, OrderState::Live | OrderState::PartiallyFilled)
+                    && o.prev_state != OrderState::Created
+                    && o.age(now) >= ttl
+            }) {
+                if reprice
+                    && !order.amended
+                    && self.price > 0.0
+                    && order
+                        .amend(broker, exchange.enable_trading, auth, self.price, now)
+                        .await?
+                {
+                    log::info!(
+                        "[{}] {} order {} re-priced to {}",
+                        self.instid,
+                        order.side.to_string(),
+                        order.id,
+                        order.px
+                    );
+                    continue;
+                }
+                if order.cancel(broker, exchange.enable_trading, auth).await? {
+                    log::info!(
+                        "[{}] {} order {} cancelled after {}s",
+                        self.instid,
+                        order.side.to_string(),
+                        order.id,
+                        exchange.order_ttl
+                    );
+                    self.status = Status::from_order(order);
+                }
             }
         }
         Ok(self)
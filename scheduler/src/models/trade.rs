@@ -20,8 +20,19 @@ pub struct Order {
     #[serde(skip_serializing)]
     pub prev_state: OrderState,
     pub strategy: String,
+    //Average fill price/size and fee (USDT) when known. Set by the paper fill simulator.
+    //Not part of the exchange request body, saved by `Order::save`
+    #[serde(default, skip_serializing)]
+    pub fill_px: f64,
+    #[serde(default, skip_serializing)]
+    pub fill_sz: f64,
+    #[serde(default, skip_serializing)]
+    pub fee: f64,
     #[serde(skip_serializing)]
-    pub response: Option<OkxOrderResponse>,
+    pub response: Option<OrderResponse>,
+    //Re-priced after reaching order_ttl, cancelled the next time
+    #[serde(skip)]
+    pub amended: bool,
 }
 
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
@@ -54,7 +65,7 @@ impl FromStr for OrderState {
             "live" => Ok(Self::Live),
             "partially_filled" => Ok(Self::PartiallyFilled),
             "filled" => Ok(Self::Filled),
-            "cancelled" => Ok(Self::Cancelled),
+            "cancelled" | "canceled" => Ok(Self::Cancelled),
             _ => Err(()),
         }
     }
@@ -68,6 +79,12 @@ pub enum ExitReason {
     FloorReached,
     Timeout,
     Cashout,
+    //Risk limits breached, liquidating the portfolio
+    Halted,
+    TrailingStop,
+    BreakEven,
+    //Reached the cashout target lowered by min_cashout
+    DecayedCashout,
 }
 #[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize, Clone)]
 pub enum Side {
@@ -104,6 +121,10 @@ impl ToString for ExitReason {
             Self::FloorReached => "floor_reached".to_string(),
             Self::Timeout => "timeout".to_string(),
             Self::Cashout => "cashout".to_string(),
+            Self::Halted => "halted".to_string(),
+            Self::TrailingStop => "trailing_stop".to_string(),
+            Self::BreakEven => "break_even".to_string(),
+            Self::DecayedCashout => "decayed_cashout".to_string(),
         }
     }
 }
@@ -119,6 +140,10 @@ impl FromStr for ExitReason {
             "floor_reached" => Ok(Self::FloorReached),
             "timeout" => Ok(Self::Timeout),
             "cashout" => Ok(Self::Cashout),
+            "halted" => Ok(Self::Halted),
+            "trailing_stop" => Ok(Self::TrailingStop),
+            "break_even" => Ok(Self::BreakEven),
+            "decayed_cashout" => Ok(Self::DecayedCashout),
             _ => Err(()),
         }
     }
@@ -143,84 +168,98 @@ impl Order {
             px: price,
             sz: size,
             strategy: strategy.to_string(),
+            fill_px: 0.0,
+            fill_sz: 0.0,
+            fee: 0.0,
             response: None,
+            amended: false,
             prev_state: OrderState::Created,
             state: OrderState::Live,
             ts: Utc::now().timestamp_millis().to_string(),
         }
     }
 
-    pub async fn get_state(&self, auth: &Authentication) -> Result<trade::OrderState> {
-        let inst_id = self.inst_id.clone();
-        let query = &format!("?ordId={ord_id}&instId={inst_id}", ord_id = self.id);
-        let signed = auth.sign(
-            "GET",
-            ORDERS_ENDPOINT,
-            OffsetDateTime::now_utc(),
-            false,
-            query,
-        )?;
-
-        let res = reqwest::Client::new()
-            .get(format!("{BASE_URL}{ORDERS_ENDPOINT}{query}"))
-            .header("OK-ACCESS-KEY", &auth.access_key)
-            .header("OK-ACCESS-PASSPHRASE", &auth.passphrase)
-            .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
-            .header("OK-ACCESS-SIGN", signed.signature.as_str())
-            .send()
-            .await?
-            .json::<OkxOrderDetailsResponse>()
-            .await?;
-        let order_state =
-            trade::OrderState::from_str(&res.data[0].state).unwrap_or(trade::OrderState::Cancelled);
-        Ok(order_state)
+    pub async fn get_state(
+        &self,
+        broker: &dyn Broker,
+        auth: &Authentication,
+    ) -> Result<trade::OrderState> {
+        broker.order_state(self, auth).await
     }
 
-    pub async fn publish(&mut self, trade_enabled: bool, auth: &Authentication) -> Result<()> {
-        let json_body = serde_json::to_string(&self)?;
+    /// Time since the order was placed (or last amended)
+    pub fn age(&self, now: DateTime<Utc>) -> Duration {
+        Duration::milliseconds(now.timestamp_millis() - self.ts.parse::<i64>().unwrap_or_default())
+    }
 
-        if trade_enabled {
-            let signed = auth.sign(
-                "POST",
-                ORDERS_ENDPOINT,
-                OffsetDateTime::now_utc(),
-                false,
-                &json_body,
-            )?;
+    /// Cancel the order on the exchange (locally on paper). Returns false if the exchange
+    /// rejected it, the next state check will tell why
+    pub async fn cancel(
+        &mut self,
+        broker: &dyn Broker,
+        trade_enabled: bool,
+        auth: &Authentication,
+    ) -> Result<bool> {
+        if trade_enabled && !broker.cancel_order(self, auth).await? {
+            return Ok(false);
+        }
+        self.state = OrderState::Cancelled;
+        Ok(true)
+    }
 
-            let res = reqwest::Client::new()
-                .post(format!("{BASE_URL}{ORDERS_ENDPOINT}"))
-                .header("OK-ACCESS-KEY", auth.access_key.clone())
-                .header("OK-ACCESS-PASSPHRASE", auth.passphrase.clone())
-                .header("OK-ACCESS-TIMESTAMP", signed.timestamp.as_str())
-                .header("OK-ACCESS-SIGN", signed.signature.as_str())
-                .json(&self)
-                .send()
-                .await?;
+    /// Move the order to a new price, restarting its ttl
+    pub async fn amend(
+        &mut self,
+        broker: &dyn Broker,
+        trade_enabled: bool,
+        auth: &Authentication,
+        price: f64,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let px = price.to_string();
+        if trade_enabled && !broker.amend_order(self, &px, auth).await? {
+            return Ok(false);
+        }
+        self.px = px;
+        self.ts = now.timestamp_millis().to_string();
+        self.amended = true;
+        Ok(true)
+    }
 
-            if res.status().is_success() {
-                let order_response = res.json::<OkxOrderResponse>().await;
-                match order_response {
-                    Ok(res) => {
-                        self.response = Some(res.clone());
-                        self.id = res.data[0].ord_id.clone();
-                    },
-                    Err(e) => {
-                        self.response = None;
-                        log::error!("{:?}", e);
-                    },
-                };
-            } else {
-                let body = res.text().await?;
-                log::error!("{}", body);
-            };
+    pub async fn publish(
+        &mut self,
+        broker: &dyn Broker,
+        trade_enabled: bool,
+        auth: &Authentication,
+    ) -> Result<()> {
+        if trade_enabled {
+            self.response = broker.place_order(self, auth).await?;
+            if let Some(res) = &self.response {
+                self.id = res.ord_id.clone();
+            }
+        } else {
+            self.id = self.cl_ord_id.clone();
         }
         Ok(())
     }
 
+    /// Filled (price, size), falling back to the order price/size when fills aren't tracked
+    pub fn filled(&self) -> Option<(f64, f64)> {
+        if self.fill_sz > 0.0 {
+            return Some((self.fill_px, self.fill_sz));
+        }
+        match (self.px.parse::<f64>(), self.sz.parse::<f64>()) {
+            (Ok(price), Ok(size)) => Some((price, size)),
+            _ => None,
+        }
+    }
+
     pub async fn save(&self, db_session: &Session) -> Result<QueryResult> {
-        let payload = serde_json::to_string_pretty(&self).unwrap();
-        let payload = payload.replace("null", "0");
+        let mut payload = serde_json::to_value(self)?;
+        payload["fill_px"] = self.fill_px.into();
+        payload["fill_sz"] = self.fill_sz.into();
+        payload["fee"] = self.fee.into();
+        let payload = serde_json::to_string_pretty(&payload)?.replace("null", "0");
         let query = format!("INSERT INTO okx.orders JSON '{}'", payload);
         Ok(db_session.query(&*query, &[]).await?)
     }
@@ -13,14 +13,29 @@ pub use serde_with::{DurationMilliSeconds, DurationSeconds};
 pub use uuid::Uuid;
 
 pub use crate::{
+    alerts::{Alert, AlertEngine, AlertRule},
     app::App,
+    events::{Event, Publisher},
+    exchange::{
+        okx::*,
+        paper::{FillSimulator, Quote},
+        Broker, ExitState, OrderResponse,
+    },
+    indicators::Indicators,
     models::{
         account::{Account, Balance},
+        book::{BookDepth, OrderEntry},
+        instrument::{Instrument, Instruments},
         report::Report,
         token::{self, Candlestick, Status, Token},
         trade::{self, ExitReason, Order, Side, State as OrderState},
     },
-    okx::*,
+    recovery::Snapshot,
+    regime::{MarketRegime, RegimeDetector},
+    risk::{Exposure, Halt, HaltReason, RiskManager},
+    search::History,
+    sizing::{PositionSizer, SizingMode},
+    strategies::TradingStrategy,
+    strategy::StrategyWatcher,
     utils::*,
-    BALANCE_ENDPOINT, BASE_URL, ORDERS_ENDPOINT,
 };
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Order fields needed to resume it. `Order` serializes in the exchange format and skips its state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedOrder {
+    pub id: String,
+    pub inst_id: String,
+    pub td_mode: String,
+    pub cl_ord_id: String,
+    pub side: Side,
+    pub ord_type: String,
+    pub px: String,
+    pub sz: String,
+    pub ts: String,
+    pub state: OrderState,
+    pub prev_state: OrderState,
+    pub strategy: String,
+    pub fill_px: f64,
+    pub fill_sz: f64,
+    pub fee: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedToken {
+    pub token: Token,
+    pub orders: Vec<SavedOrder>,
+}
+
+/// Account and portfolio state saved to `okx.snapshots` every round, so a restarted
+/// scheduler keeps managing the open positions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub balance: Balance,
+    pub earnings: f64,
+    pub trades: u64,
+    pub fee_spend: f64,
+    pub change: f32,
+    pub portfolio: Vec<SavedToken>,
+    pub deny_list: Vec<String>,
+    //Milliseconds left before tracked tokens can be bought again
+    pub cooldowns: HashMap<String, i64>,
+    pub round_id: u64,
+    //Strategies the portfolio was bought with, by hash
+    pub strategies: HashMap<String, Strategy>,
+}
+
+impl From<&Order> for SavedOrder {
+    fn from(order: &Order) -> Self {
+        Self {
+            id: order.id.clone(),
+            inst_id: order.inst_id.clone(),
+            td_mode: order.td_mode.clone(),
+            cl_ord_id: order.cl_ord_id.clone(),
+            side: order.side.clone(),
+            ord_type: order.ord_type.clone(),
+            px: order.px.clone(),
+            sz: order.sz.clone(),
+            ts: order.ts.clone(),
+            state: order.state.clone(),
+            prev_state: order.prev_state.clone(),
+            strategy: order.strategy.clone(),
+            fill_px: order.fill_px,
+            fill_sz: order.fill_sz,
+            fee: order.fee,
+        }
+    }
+}
+
+impl From<SavedOrder> for Order {
+    fn from(saved: SavedOrder) -> Self {
+        Self {
+            id: saved.id,
+            inst_id: saved.inst_id,
+            td_mode: saved.td_mode,
+            cl_ord_id: saved.cl_ord_id,
+            side: saved.side,
+            ord_type: saved.ord_type,
+            px: saved.px,
+            sz: saved.sz,
+            ts: saved.ts,
+            state: saved.state,
+            prev_state: saved.prev_state,
+            strategy: saved.strategy,
+            fill_px: saved.fill_px,
+            fill_sz: saved.fill_sz,
+            fee: saved.fee,
+            response: None,
+            amended: false,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new(app: &App, account: &Account) -> Self {
+        let portfolio = account
+            .portfolio
+            .iter()
+            .map(|t| {
+                let mut token = t.clone();
+                let orders = token.orders.take().unwrap_or_default();
+                SavedToken {
+                    token,
+                    orders: orders.iter().map(SavedOrder::from).collect(),
+                }
+            })
+            .collect();
+        let strategies = app
+            .strategies
+            .iter()
+            .filter(|(hash, _)| account.portfolio.iter().any(|t| &t.strategy == *hash))
+            .map(|(hash, strategy)| (hash.clone(), strategy.clone()))
+            .collect();
+
+        Self {
+            balance: account.balance.clone(),
+            earnings: account.earnings,
+            trades: account.trades,
+            fee_spend: account.fee_spend,
+            change: account.change,
+            portfolio,
+            deny_list: app.deny_list.clone(),
+            cooldowns: app
+                .tokens
+                .iter()
+                .map(|t| (t.instid.clone(), t.cooldown.num_milliseconds()))
+                .collect(),
+            round_id: app.round_id,
+            strategies,
+        }
+    }
+
+    pub async fn save(&self, db_session: &Session, exchange: &str, ts: i64) -> Result<QueryResult> {
+        let state = serde_json::to_string(&self)?;
+        Ok(db_session
+            .query(
+                "INSERT INTO okx.snapshots (exchange, state, ts) VALUES (?, ?, ?)",
+                (exchange, state, ts),
+            )
+            .await?)
+    }
+
+    /// Latest snapshot of the exchange and its age in milliseconds
+    pub async fn load(db_session: &Session, exchange: &str) -> Result<Option<(Self, i64)>> {
+        let query = "SELECT state, ts FROM okx.snapshots WHERE exchange = ?";
+        if let Some(rows) = db_session.query(query, (exchange,)).await?.rows {
+            if let Some(row) = rows.into_typed::<(String, i64)>().next() {
+                let (state, ts) = row?;
+                return Ok(Some((serde_json::from_str(&state)?, ts)));
+            }
+        };
+        Ok(None)
+    }
+
+    /// Rebuild the account and the app runtime state. With trading enabled, live orders and token
+    /// balances are checked against the exchange first; tokens no longer held are dropped.
+    pub async fn restore(self, app: &mut App, mut account: Account) -> Result<Account> {
+        account.balance = self.balance;
+        account.earnings = self.earnings;
+        account.trades = self.trades;
+        account.fee_spend = self.fee_spend;
+        account.change = self.change;
+
+        for saved in self.portfolio {
+            let mut t = saved.token;
+            let mut orders: Vec<Order> = saved.orders.into_iter().map(Order::from).collect();
+
+            if app.exchange.enable_trading {
+                for order in orders.iter_mut().filter(|o| {
+                    matches!(o.state, OrderState::Live | OrderState::PartiallyFilled)
+                        && !o.id.is_empty()
+                }) {
+                    let state = order
+                        .get_state(app.broker.as_ref(), &app.exchange.authentication)
+                        .await?;
+                    if state != order.state {
+                        app.logs.push(format!(
+                            "[{}] {} order {} is now {}",
+                            t.instid,
+                            order.side.to_string(),
+                            order.id,
+                            state.to_string()
+                        ));
+                        order.state = state;
+                    }
+                }
+
+                if matches!(t.status, Status::Trading | Status::Selling) {
+                    let balance = Account::get_balance(
+                        app.broker.as_ref(),
+                        &t.instid.replace("-USDT", ""),
+                        &app.exchange.authentication,
+                    )
+                    .await?;
+                    let live_orders = orders
+                        .iter()
+                        .any(|o| matches!(o.state, OrderState::Live | OrderState::PartiallyFilled));
+                    if balance * t.price <= 2.0 && !live_orders {
+                        app.logs.push(format!(
+                            "[{}] No balance left on the exchange, removing it from the portfolio",
+                            t.instid
+                        ));
+                        continue;
+                    }
+                    t.balance.available = balance;
+                    t.balance.current = balance;
+                }
+            }
+
+            t.orders = if orders.is_empty() {
+                None
+            } else {
+                Some(orders)
+            };
+            account.portfolio.push(t);
+        }
+
+        for (hash, mut strategy) in self.strategies {
+            strategy.hash = hash.clone();
+            app.strategies.insert(hash, strategy);
+        }
+        for token in self.deny_list {
+            if !app.deny_list.contains(&token) {
+                app.deny_list.push(token);
+            }
+        }
+        app.tokens = self
+            .cooldowns
+            .into_iter()
+            .map(|(instid, cooldown)| {
+                let mut t = Token::new(&instid);
+                t.cooldown = Duration::milliseconds(cooldown);
+                t
+            })
+            .collect();
+        app.round_id = self.round_id;
+        Ok(account)
+    }
+}
@@ -0,0 +1,145 @@
+use exchange_observer::Regime;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MarketRegime {
+    RiskOn,
+    #[default]
+    Neutral,
+    RiskOff,
+}
+
+impl ToString for MarketRegime {
+    fn to_string(&self) -> String {
+        match self {
+            Self::RiskOn => "risk-on",
+            Self::Neutral => "neutral",
+            Self::RiskOff => "risk-off",
+        }
+        .to_string()
+    }
+}
+
+/// Classifies the market from the change of the reference instruments over several timeframes
+#[derive(Debug, Clone, Default)]
+pub struct RegimeDetector {
+    cfg: Regime,
+    pub regime: MarketRegime,
+    //Change (%) by instrument and timeframe
+    pub changes: Vec<(String, i64, f32)>,
+    updated: Option<DateTime<Utc>>,
+}
+
+impl RegimeDetector {
+    pub fn new(cfg: Option<Regime>) -> Self {
+        Self {
+            cfg: cfg.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cfg.enable
+    }
+
+    fn instruments(&self) -> Vec<String> {
+        self.cfg
+            .instruments
+            .clone()
+            .unwrap_or_else(|| vec!["BTC-USDT".to_string(), "ETH-USDT".to_string()])
+    }
+
+    fn timeframes(&self) -> Vec<i64> {
+        self.cfg
+            .timeframes
+            .clone()
+            .unwrap_or_else(|| vec![15, 60, 240])
+    }
+
+    /// Refresh the regime once a minute. Returns the new regime when it changed
+    pub async fn update(
+        &mut self,
+        db_session: &Session,
+        now: DateTime<Utc>,
+    ) -> Result<Option<MarketRegime>> {
+        if !self.cfg.enable
+            || self
+                .updated
+                .map_or(false, |ts| now - ts < Duration::minutes(1))
+        {
+            return Ok(None);
+        }
+        self.updated = Some(now);
+
+        let timeframes = self.timeframes();
+        let limit = timeframes.iter().copied().max().unwrap_or(0) as i32;
+        let query = "SELECT * FROM candle1m WHERE instid=? AND ts <= ? LIMIT ?";
+        let mut changes = Vec::new();
+        for instid in self.instruments() {
+            //Newest first
+            let mut candles = Vec::new();
+            if let Some(rows) = db_session
+                .query(query, (&instid, now.timestamp_millis(), limit))
+                .await?
+                .rows
+            {
+                for row in rows.into_typed::<Candlestick>() {
+                    candles.push(row?);
+                }
+            }
+            for timeframe in timeframes.iter() {
+                let window = &candles[..candles.len().min(*timeframe as usize)];
+                if let (Some(last), Some(first)) = (window.first(), window.last()) {
+                    if first.open > 0.0 {
+                        let change = get_percentage_diff(last.close, first.open);
+                        changes.push((instid.clone(), *timeframe, change));
+                    }
+                }
+            }
+        }
+        self.changes = changes;
+
+        let previous = self.regime;
+        self.regime = self.classify();
+        Ok(if self.regime != previous {
+            Some(self.regime)
+        } else {
+            None
+        })
+    }
+
+    fn classify(&self) -> MarketRegime {
+        if self.changes.is_empty() {
+            return MarketRegime::Neutral;
+        }
+        let average =
+            self.changes.iter().map(|(_, _, c)| c).sum::<f32>() / self.changes.len() as f32;
+        if average <= self.cfg.risk_off {
+            MarketRegime::RiskOff
+        } else if average >= self.cfg.risk_on {
+            MarketRegime::RiskOn
+        } else {
+            MarketRegime::Neutral
+        }
+    }
+
+    /// Whether new buys are paused by the strategy in the current regime
+    pub fn pauses(&self, strategy: &Strategy) -> bool {
+        self.regime == MarketRegime::RiskOff && strategy.risk_off_pause.unwrap_or(false)
+    }
+
+    /// Strategy with its risk-off limits applied. The hash is kept so positions stay linked to it
+    pub fn adjust(&self, strategy: &Strategy) -> Strategy {
+        let mut strategy = strategy.clone();
+        if self.regime == MarketRegime::RiskOff {
+            if let Some(size) = strategy.risk_off_portfolio_size {
+                strategy.portfolio_size = strategy.portfolio_size.min(size);
+            }
+            if let Some(stoploss) = strategy.risk_off_stoploss {
+                strategy.stoploss = strategy.stoploss.min(stoploss);
+            }
+        }
+        strategy
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDate;
+use exchange_observer::Risk;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HaltReason {
+    MaxDrawdown(f64),
+    MaxDrawdownPct(f32),
+    ConsecutiveStoplosses(u32),
+    DailyLoss(f64),
+}
+
+impl ToString for HaltReason {
+    fn to_string(&self) -> String {
+        match self {
+            Self::MaxDrawdown(loss) => format!("max drawdown reached: {:.2} USDT", loss),
+            Self::MaxDrawdownPct(pct) => format!("max drawdown reached: {:.2}%", pct),
+            Self::ConsecutiveStoplosses(count) => format!("{} consecutive stoplosses", count),
+            Self::DailyLoss(loss) => format!("daily loss limit reached: {:.2} USDT", loss),
+        }
+    }
+}
+
+/// Halt record saved to `okx.halts`. The scheduler refuses to start while the latest one
+/// isn't acknowledged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Halt {
+    pub exchange: String,
+    pub reason: String,
+    pub balance: f64,
+    pub strategy: String,
+    pub acknowledged: bool,
+    pub ts: String,
+}
+
+impl Halt {
+    pub async fn save(&self, db_session: &Session) -> Result<QueryResult> {
+        let payload = serde_json::to_string_pretty(&self).unwrap();
+        let query = format!("INSERT INTO okx.halts JSON '{}'", payload);
+        Ok(db_session.query(&*query, &[]).await?)
+    }
+
+    /// Latest halt for the exchange, if it wasn't acknowledged yet
+    pub async fn pending(db_session: &Session, exchange: &str) -> Result<Option<Self>> {
+        let query = format!(
+            "select reason, balance, strategy, acknowledged, ts from okx.halts where exchange='{}' limit 1;",
+            exchange
+        );
+        if let Some(rows) = db_session.query(&*query, &[]).await?.rows {
+            for row in rows.into_typed::<(String, f64, String, bool, Duration)>() {
+                let (reason, balance, strategy, acknowledged, ts) = row?;
+                if !acknowledged {
+                    return Ok(Some(Self {
+                        exchange: exchange.to_string(),
+                        reason,
+                        balance,
+                        strategy,
+                        acknowledged,
+                        ts: ts.num_milliseconds().to_string(),
+                    }));
+                }
+            }
+        };
+        Ok(None)
+    }
+
+    pub async fn acknowledge(&mut self, db_session: &Session) -> Result<QueryResult> {
+        self.acknowledged = true;
+        self.save(db_session).await
+    }
+}
+
+impl ToString for Halt {
+    fn to_string(&self) -> String {
+        let ts = self.ts.parse::<i64>().unwrap_or_default();
+        format!(
+            "Trading halted on {} at {}: {} - Balance: {:.2} - Strategy: {:.7}",
+            self.exchange,
+            Utc.timestamp_millis_opt(ts)
+                .single()
+                .unwrap_or_default()
+                .format("%Y-%m-%d %H:%M:%S"),
+            self.reason,
+            self.balance,
+            self.strategy
+        )
+    }
+}
+
+/// Tracks the account against the `[risk]` limits
+#[derive(Debug, Clone, Default)]
+pub struct RiskManager {
+    pub cfg: Risk,
+    pub peak: f64,
+    pub day: Option<NaiveDate>,
+    pub day_start: f64,
+    pub consecutive_stoplosses: u32,
+    //Rounds already counted, sells are retried until filled
+    exits: HashSet<u64>,
+    //Closed rounds by exit reason
+    pub exit_reasons: BTreeMap<String, u64>,
+    pub halted: Option<HaltReason>,
+}
+
+impl RiskManager {
+    pub fn new(cfg: Option<Risk>) -> Self {
+        Self {
+            cfg: cfg.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted.is_some()
+    }
+
+    /// Returns the breached limit the first time the account goes over one
+    pub fn check(&mut self, account: &Account, now: DateTime<Utc>) -> Option<HaltReason> {
+        if !self.cfg.enable || self.is_halted() {
+            return None;
+        }
+        let balance = account.balance.current;
+        self.peak = self.peak.max(balance);
+        if self.day != Some(now.date_naive()) {
+            self.day = Some(now.date_naive());
+            self.day_start = balance;
+        }
+
+        let drawdown = self.peak - balance;
+        let drawdown_pct = if self.peak > 0.0 {
+            (drawdown / self.peak * 100.0) as f32
+        } else {
+            0.0
+        };
+        let daily_loss = self.day_start - balance;
+
+        let cfg = &self.cfg;
+        let reason = if cfg.max_drawdown.map_or(false, |max| drawdown >= max) {
+            Some(HaltReason::MaxDrawdown(drawdown))
+        } else if cfg
+            .max_drawdown_pct
+            .map_or(false, |max| drawdown_pct >= max)
+        {
+            Some(HaltReason::MaxDrawdownPct(drawdown_pct))
+        } else if cfg
+            .max_consecutive_stoplosses
+            .map_or(false, |max| self.consecutive_stoplosses >= max)
+        {
+            Some(HaltReason::ConsecutiveStoplosses(
+                self.consecutive_stoplosses,
+            ))
+        } else if cfg.daily_loss_limit.map_or(false, |max| daily_loss >= max) {
+            Some(HaltReason::DailyLoss(daily_loss))
+        } else {
+            None
+        };
+        self.halted = reason.clone();
+        reason
+    }
+
+    /// Count stoplosses in a row. Any other exit resets the streak
+    pub fn record_exit(&mut self, round_id: u64, reason: Option<&ExitReason>) {
+        if !self.exits.insert(round_id) {
+            return;
+        }
+        let name = reason.map_or_else(|| "none".to_string(), |r| r.to_string());
+        *self.exit_reasons.entry(name).or_default() += 1;
+        match reason {
+            Some(ExitReason::Stoploss) => self.consecutive_stoplosses += 1,
+            Some(ExitReason::Halted) => (),
+            _ => self.consecutive_stoplosses = 0,
+        }
+    }
+
+    /// USDT held in the portfolio, counting pending buys at their position size
+    pub fn exposure(account: &Account) -> f64 {
+        account
+            .portfolio
+            .iter()
+            .map(|t| match t.status {
+                Status::Buying => t.allocated(account.balance.spendable),
+                _ => t.balance.current * t.price,
+            })
+            .sum()
+    }
+
+    /// Whether a new token of `size` USDT can be added without going over `max_exposure`
+    pub fn can_open(&self, account: &Account, size: f64) -> bool {
+        if self.is_halted() {
+            return false;
+        }
+        match self.cfg.max_exposure {
+            Some(max) if self.cfg.enable => RiskManager::exposure(account) + size <= max,
+            _ => true,
+        }
+    }
+}
+
+/// Portfolio level exposure, shown in the dashboard
+#[derive(Debug, Clone, Default)]
+pub struct Exposure {
+    //USDT in open positions and pending buys
+    pub usdt: f64,
+    //% of the current balance
+    pub pct: f32,
+    pub positions: usize,
+    //% of the exposure in the biggest position
+    pub largest_pct: f32,
+    //Pairwise correlation of the held tokens candle changes
+    pub avg_correlation: Option<f32>,
+    pub max_correlation: Option<f32>,
+}
+
+impl Exposure {
+    pub fn new(account: &Account) -> Self {
+        let held: Vec<&Token> = account
+            .portfolio
+            .iter()
+            .filter(|t| t.status != Status::Exited)
+            .collect();
+        let usdt = RiskManager::exposure(account);
+        let largest = held
+            .iter()
+            .map(|t| match t.status {
+                Status::Buying => t.allocated(account.balance.spendable),
+                _ => t.balance.current * t.price,
+            })
+            .fold(0.0, f64::max);
+        let correlations: Vec<f32> = held
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| held[i + 1..].iter().filter_map(|b| a.correlation(b)))
+            .collect();
+        let pct = |value: f64, total: f64| {
+            if total > 0.0 {
+                (value / total * 100.0) as f32
+            } else {
+                0.0
+            }
+        };
+        Self {
+            usdt,
+            pct: pct(usdt, account.balance.current),
+            positions: held.len(),
+            largest_pct: pct(largest, usdt),
+            avg_correlation: mean(&correlations),
+            max_correlation: correlations.iter().copied().reduce(f32::max),
+        }
+    }
+}
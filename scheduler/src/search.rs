@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use exchange_observer::Search;
+use rand::Rng;
+
+use crate::{
+    backtest::{Backtest, Summary},
+    prelude::*,
+};
+
+const DEFAULT_RUNS: u32 = 10;
+//Give up after sampling (x) times the requested runs without finding new strategies
+const MAX_SAMPLES_PER_RUN: u32 = 50;
+
+/// Earnings of every round closed with a strategy, from okx.reports
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    pub rounds: u64,
+    pub earnings: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StrategySearch {
+    pub runs: u32,
+    pub force: bool,
+    //Backtest window. Without it a single candidate is written to `output` to be evaluated live
+    pub backtest: Option<Backtest>,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub strategy: Strategy,
+    pub summary: Summary,
+    //Net earnings (fees included) minus the max drawdown in USDT
+    pub score: f64,
+}
+
+impl History {
+    /// Rounds and earnings by strategy hash
+    pub async fn load(db_session: &Session) -> Result<HashMap<String, History>> {
+        let mut history: HashMap<String, History> = HashMap::new();
+        let query = "SELECT strategy, earnings FROM okx.reports";
+        if let Some(rows) = db_session.query(query, &[]).await?.rows {
+            for row in rows.into_typed::<(String, f64)>() {
+                let (strategy, earnings) = row?;
+                let entry = history.entry(strategy).or_default();
+                entry.rounds += 1;
+                entry.earnings += earnings;
+            }
+        };
+        Ok(history)
+    }
+
+    /// Error out if the strategy already lost money, unless forced
+    pub async fn check(db_session: &Session, strategy: &Strategy, force: bool) -> Result<()> {
+        let history = History::load(db_session).await?;
+        match history.get(&strategy.hash) {
+            Some(h) if h.earnings < 0.0 => {
+                let msg = format!(
+                    "Strategy {:.7} lost {:.2} USDT in {} previous rounds",
+                    strategy.hash, h.earnings, h.rounds
+                );
+                if !force {
+                    return Err(anyhow::anyhow!("{}. Use --force to run it anyway", msg));
+                }
+                log::warn!("{}", msg);
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrategySearch {
+    /// Parse `search [--from <ts> --to <ts>] [--step <secs>] [--runs <n>] [--output <file>] [--force]`
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut runs = DEFAULT_RUNS;
+        let mut force = false;
+        let mut output = None;
+        let mut backtest_args = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--force" {
+                force = true;
+                backtest_args.push(arg.to_string());
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for argument {}", arg))?;
+            match arg.as_str() {
+                "--runs" => runs = value.parse::<u32>()?,
+                "--output" => output = Some(value.to_string()),
+                "--from" | "--to" | "--step" => {
+                    backtest_args.push(arg.to_string());
+                    backtest_args.push(value.to_string());
+                },
+                _ => return Err(anyhow::anyhow!("Unknown argument: {}", arg)),
+            }
+        }
+
+        let backtest = if !backtest_args.iter().any(|a| a == "--from" || a == "--to") {
+            None
+        } else {
+            Some(Backtest::from_args(&backtest_args)?)
+        };
+        if backtest.is_none() && output.is_none() {
+            return Err(anyhow::anyhow!(
+                "Usage: scheduler search --from <ts> --to <ts> [--step <secs>] [--runs <n>] [--force]\n       scheduler search --output <strategy.toml> [--force]"
+            ));
+        }
+        if runs == 0 {
+            return Err(anyhow::anyhow!("--runs should be at least 1"));
+        }
+
+        Ok(Self {
+            runs,
+            force,
+            backtest,
+            output,
+        })
+    }
+
+    pub async fn run(&self, mut cfg: AppConfig) -> Result<Vec<Candidate>, Box<dyn Error>> {
+        let bounds = cfg.search.clone().unwrap_or_default();
+        let base = cfg.strategy.clone();
+
+        let app = App::init(&cfg).await?;
+        let history = History::load(&app.db_session).await?;
+        let known = Self::known_strategies(&app.db_session).await?;
+
+        let runs = if self.backtest.is_some() {
+            self.runs
+        } else {
+            1
+        };
+        let candidates = self.sample(&base, &bounds, runs, &known, &history);
+        if candidates.is_empty() {
+            return Err("No new strategies left to try within the [search] bounds".into());
+        }
+
+        //Live evaluation: the scheduler picks the candidate up from its strategy file (paper trading only)
+        let Some(backtest) = &self.backtest else {
+            let path = self.output.as_deref().unwrap_or_default();
+            if cfg.exchange.clone().unwrap_or_default().enable_trading {
+                return Err(
+                    "Candidates are only evaluated live with exchange.enable_trading=false".into(),
+                );
+            }
+            candidates[0].save(path)?;
+            log::info!("Strategy {} written to {}", candidates[0].hash, path);
+            return Ok(Vec::new());
+        };
+
+        let mut results = Vec::new();
+        for (i, strategy) in candidates.into_iter().enumerate() {
+            log::info!("Evaluating strategy {}/{}: {}", i + 1, runs, strategy.hash);
+            cfg.strategy = strategy.clone();
+            let summary = backtest.run(cfg.clone()).await?;
+            let drawdown = cfg.account.balance * summary.max_drawdown as f64 / 100.0;
+            results.push(Candidate {
+                score: summary.earnings - drawdown,
+                strategy,
+                summary,
+            });
+        }
+
+        results.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for (rank, c) in results.iter().enumerate() {
+            log::info!(
+                "#{} score: {:.2} | {}",
+                rank + 1,
+                c.score,
+                c.summary.to_string()
+            );
+        }
+        Ok(results)
+    }
+
+    /// Hashes saved to okx.strategies or referenced by okx.reports
+    async fn known_strategies(db_session: &Session) -> Result<HashSet<String>> {
+        let mut known = HashSet::new();
+        for query in [
+            "SELECT hash FROM okx.strategies",
+            "SELECT strategy FROM okx.reports",
+        ] {
+            if let Some(rows) = db_session.query(query, &[]).await?.rows {
+                for row in rows.into_typed::<(String,)>() {
+                    known.insert(row?.0);
+                }
+            };
+        }
+        Ok(known)
+    }
+
+    /// Valid strategies that weren't tried before. Known ones are only repeated with --force
+    fn sample(
+        &self,
+        base: &Strategy,
+        bounds: &Search,
+        runs: u32,
+        known: &HashSet<String>,
+        history: &HashMap<String, History>,
+    ) -> Vec<Strategy> {
+        let mut rng = rand::thread_rng();
+        let mut candidates: Vec<Strategy> = Vec::new();
+
+        for _ in 0..runs * MAX_SAMPLES_PER_RUN {
+            if candidates.len() >= runs as usize {
+                break;
+            }
+            let strategy = Self::randomize(base, bounds, &mut rng);
+            if strategy.validate().is_err() || candidates.iter().any(|c| c.hash == strategy.hash) {
+                continue;
+            }
+            if known.contains(&strategy.hash) {
+                match history.get(&strategy.hash) {
+                    Some(h) if self.force && h.earnings < 0.0 => log::warn!(
+                        "Re-running strategy {} that lost {:.2} USDT",
+                        strategy.hash,
+                        h.earnings
+                    ),
+                    _ if self.force => (),
+                    _ => {
+                        log::debug!("Skipping known strategy {}", strategy.hash);
+                        continue;
+                    },
+                }
+            }
+            candidates.push(strategy);
+        }
+        candidates
+    }
+
+    fn randomize(base: &Strategy, bounds: &Search, rng: &mut impl Rng) -> Strategy {
+        //Rounded to 2 decimals so close values share the same hash
+        let mut float = |bounds: Option<(f32, f32)>, value: f32| match bounds {
+            Some((min, max)) if min < max => (rng.gen_range(min..=max) * 100.0).round() / 100.0,
+            Some((min, _)) => min,
+            None => value,
+        };
+        let mut strategy = base.clone();
+        strategy.min_change = float(bounds.min_change, base.min_change);
+        strategy.min_deviation = float(bounds.min_deviation, base.min_deviation);
+        strategy.max_deviation = float(bounds.max_deviation, base.max_deviation);
+        strategy.stoploss = float(bounds.stoploss, base.stoploss);
+        strategy.cashout = float(bounds.cashout, base.cashout);
+        if bounds.sell_floor.is_some() {
+            strategy.sell_floor = Some(float(bounds.sell_floor, 0.0));
+        }
+
+        let mut int = |bounds: Option<(i64, i64)>, value: i64| match bounds {
+            Some((min, max)) if min < max => rng.gen_range(min..=max),
+            Some((min, _)) => min,
+            None => value,
+        };
+        strategy.timeframe = int(bounds.timeframe, base.timeframe);
+        strategy.timeout = int(bounds.timeout, base.timeout);
+
+        strategy.hash = strategy.get_hash();
+        strategy
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use exchange_observer::Sizing;
+
+use crate::prelude::*;
+
+//Minutes before the rounds history of a token is read again
+const HISTORY_TTL_MINS: i64 = 10;
+
+//(change, ts) of each round
+type Rounds = Vec<(f32, Duration)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizingMode {
+    Fixed,
+    //% of the current balance
+    Balance(f64),
+    //% of the average candle volume
+    Volume(f64),
+    //Target ATR, % of the price
+    Volatility(f64),
+    Kelly { fraction: f64, min_trades: usize },
+}
+
+impl ToString for SizingMode {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Fixed => "fixed",
+            Self::Balance(_) => "balance",
+            Self::Volume(_) => "volume",
+            Self::Volatility(_) => "volatility",
+            Self::Kelly { .. } => "kelly",
+        }
+        .to_string()
+    }
+}
+
+/// Picks the USDT amount to spend on each buy
+#[derive(Debug, Clone)]
+pub struct PositionSizer {
+    pub mode: SizingMode,
+    max_spendable: f64,
+    //Kelly rounds history and its load time, by instrument
+    history: HashMap<String, (DateTime<Utc>, Rounds)>,
+}
+
+impl PositionSizer {
+    pub fn new(cfg: Option<Sizing>, spendable: f64) -> Result<Self> {
+        let cfg = cfg.unwrap_or_default();
+        let required = |value: Option<f64>, name: &str| {
+            value
+                .filter(|v| *v > 0.0)
+                .ok_or_else(|| anyhow::anyhow!("Invalid sizing: {} should be above 0", name))
+        };
+        let mode = match cfg.mode.as_str() {
+            "" | "fixed" => SizingMode::Fixed,
+            "balance" => SizingMode::Balance(required(cfg.balance_pct, "balance_pct")?),
+            "volume" => SizingMode::Volume(required(cfg.volume_pct, "volume_pct")?),
+            "volatility" => SizingMode::Volatility(required(cfg.target_atr, "target_atr")?),
+            "kelly" => SizingMode::Kelly {
+                fraction: required(cfg.kelly_fraction, "kelly_fraction")?,
+                min_trades: cfg.kelly_min_trades.unwrap_or(10),
+            },
+            mode => return Err(anyhow::anyhow!("Invalid sizing: unknown mode {}", mode)),
+        };
+        Ok(Self {
+            mode,
+            max_spendable: cfg.max_spendable.unwrap_or(spendable),
+            history: HashMap::new(),
+        })
+    }
+
+    /// USDT to spend on the token. 0 when the token shouldn't be bought (negative Kelly edge)
+    pub async fn size(
+        &mut self,
+        token: &Token,
+        balance: &Balance,
+        db_session: &Session,
+        now: DateTime<Utc>,
+    ) -> Result<f64> {
+        let size = match self.mode {
+            SizingMode::Fixed => balance.spendable,
+            SizingMode::Balance(pct) => balance.current * pct / 100.0,
+            SizingMode::Volume(pct) => {
+                token.vol / token.candlesticks.len().max(1) as f64 * pct / 100.0
+            },
+            //Std deviation of the candle changes when there are no indicators yet
+            SizingMode::Volatility(target) => {
+                match token.indicators.atr.or(Some(token.std_deviation)) {
+                    Some(volatility) if volatility > 0.0 => {
+                        balance.spendable * target / volatility as f64
+                    },
+                    _ => balance.spendable,
+                }
+            },
+            SizingMode::Kelly {
+                fraction,
+                min_trades,
+            } => {
+                let changes = self.history(db_session, &token.instid, now).await?;
+                if changes.len() < min_trades {
+                    balance.spendable
+                } else {
+                    balance.current * kelly(&changes) * fraction
+                }
+            },
+        };
+        Ok(size.min(self.max_spendable).min(balance.available).max(0.0))
+    }
+
+    /// Final change (%) of the rounds of the token closed before `now`.
+    /// Cached for `HISTORY_TTL_MINS`, rounds after `now` are skipped so backtests don't look ahead
+    async fn history(
+        &mut self,
+        db_session: &Session,
+        instid: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<f32>> {
+        let stale = self.history.get(instid).map_or(true, |(loaded, _)| {
+            *loaded > now || now - *loaded > Duration::minutes(HISTORY_TTL_MINS)
+        });
+        if stale {
+            //Served by the reports_by_instid index
+            let query = "SELECT change, ts FROM okx.reports WHERE instid = ?";
+            let mut rounds = Vec::new();
+            if let Some(rows) = db_session.query(query, (instid,)).await?.rows {
+                for row in rows.into_typed::<(f32, Duration)>() {
+                    rounds.push(row?);
+                }
+            }
+            self.history.insert(instid.to_string(), (now, rounds));
+        }
+        let until = Duration::milliseconds(now.timestamp_millis());
+        Ok(self
+            .history
+            .get(instid)
+            .map(|(_, rounds)| {
+                rounds
+                    .iter()
+                    .filter(|(_, ts)| *ts <= until)
+                    .map(|(change, _)| *change)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Kelly fraction from the win rate and the average win/loss ratio
+fn kelly(changes: &[f32]) -> f64 {
+    let (wins, losses): (Vec<f64>, Vec<f64>) =
+        changes.iter().map(|c| *c as f64).partition(|c| *c > 0.0);
+    if wins.is_empty() {
+        return 0.0;
+    }
+    let win_rate = wins.len() as f64 / changes.len() as f64;
+    if losses.is_empty() {
+        return win_rate;
+    }
+    let avg_win = wins.iter().sum::<f64>() / wins.len() as f64;
+    let avg_loss = losses.iter().map(|l| l.abs()).sum::<f64>() / losses.len() as f64;
+    if avg_loss == 0.0 {
+        return win_rate;
+    }
+    (win_rate - (1.0 - win_rate) / (avg_win / avg_loss)).max(0.0)
+}
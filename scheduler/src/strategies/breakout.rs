@@ -0,0 +1,34 @@
+use std::cmp::Ordering;
+
+use super::TradingStrategy;
+use crate::prelude::*;
+
+/// Buys tokens closing above the highest high of the previous candles on above average
+/// volume. Biggest volume spike first
+#[derive(Debug, Clone, Copy)]
+pub struct Breakout;
+
+impl TradingStrategy for Breakout {
+    fn select(
+        &self,
+        token: &Token,
+        strategy: &Strategy,
+        deny_list: &[String],
+        spendable: f64,
+    ) -> bool {
+        let Some((last, previous)) = token.candlesticks.split_last() else {
+            return false;
+        };
+        let resistance = previous.iter().map(|c| c.high).fold(f64::MIN, f64::max);
+        token.is_tradable(deny_list, strategy, spendable)
+            && !previous.is_empty()
+            && last.close > resistance
+            && last.change > strategy.min_change_last_candle
+            && token.indicators.vol_zscore.map_or(false, |z| z > 0.0)
+    }
+
+    fn rank(&self, a: &Token, b: &Token) -> Ordering {
+        let zscore = |t: &Token| t.indicators.vol_zscore.unwrap_or(f32::MIN);
+        zscore(b).partial_cmp(&zscore(a)).unwrap_or(Ordering::Equal)
+    }
+}
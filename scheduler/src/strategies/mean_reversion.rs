@@ -0,0 +1,36 @@
+use std::cmp::Ordering;
+
+use super::TradingStrategy;
+use crate::prelude::*;
+
+/// Buys tokens that dropped at least `min_change` over the timeframe and trade below their
+/// VWAP, expecting them to bounce back. Biggest drop first
+#[derive(Debug, Clone, Copy)]
+pub struct MeanReversion;
+
+impl TradingStrategy for MeanReversion {
+    fn select(
+        &self,
+        token: &Token,
+        strategy: &Strategy,
+        deny_list: &[String],
+        spendable: f64,
+    ) -> bool {
+        token.is_tradable(deny_list, strategy, spendable)
+            && token.change <= -strategy.min_change
+            && token.std_deviation <= strategy.max_deviation
+            && token
+                .indicators
+                .vwap
+                .map_or(false, |vwap| token.price < vwap)
+    }
+
+    fn rank(&self, a: &Token, b: &Token) -> Ordering {
+        a.change.partial_cmp(&b.change).unwrap_or(Ordering::Equal)
+    }
+
+    //Dropping out of the selection is expected once the price recovers
+    fn exit(&self, token: &Token, strategy: &Strategy, _token_found: bool) -> Option<ExitReason> {
+        token.get_exit_reason(strategy, true)
+    }
+}
@@ -0,0 +1,46 @@
+use std::{cmp::Ordering, fmt::Debug};
+
+use crate::prelude::*;
+
+pub mod breakout;
+pub mod mean_reversion;
+pub mod momentum;
+
+/// Trading decisions of a strategy kind: which tokens to buy, in which order, how much and
+/// when to sell them. Settings come from the `[strategy]` config, the kind from `strategy.kind`.
+pub trait TradingStrategy: Send + Sync + Debug {
+    /// Whether the token can be bought
+    fn select(
+        &self,
+        token: &Token,
+        strategy: &Strategy,
+        deny_list: &[String],
+        spendable: f64,
+    ) -> bool;
+    /// Order of the selected tokens, best first
+    fn rank(&self, a: &Token, b: &Token) -> Ordering;
+    /// USDT to spend on the token
+    fn size(&self, _token: &Token, spendable: f64) -> f64 {
+        spendable
+    }
+    fn exit(&self, token: &Token, strategy: &Strategy, token_found: bool) -> Option<ExitReason> {
+        token.get_exit_reason(strategy, token_found)
+    }
+}
+
+static MOMENTUM: momentum::Momentum = momentum::Momentum;
+static MEAN_REVERSION: mean_reversion::MeanReversion = mean_reversion::MeanReversion;
+static BREAKOUT: breakout::Breakout = breakout::Breakout;
+
+/// Implementation for the strategy `kind`, momentum when unset
+pub fn from_strategy(strategy: &Strategy) -> Result<&'static dyn TradingStrategy> {
+    match strategy.kind.as_deref().unwrap_or("momentum") {
+        "momentum" => Ok(&MOMENTUM),
+        "mean_reversion" => Ok(&MEAN_REVERSION),
+        "breakout" => Ok(&BREAKOUT),
+        kind => Err(anyhow::anyhow!(
+            "Unknown strategy kind: {}. Use momentum, mean_reversion or breakout",
+            kind
+        )),
+    }
+}
@@ -0,0 +1,24 @@
+use std::cmp::Ordering;
+
+use super::TradingStrategy;
+use crate::prelude::*;
+
+/// Buys tokens going up steadily over the timeframe, strongest change first
+#[derive(Debug, Clone, Copy)]
+pub struct Momentum;
+
+impl TradingStrategy for Momentum {
+    fn select(
+        &self,
+        token: &Token,
+        strategy: &Strategy,
+        deny_list: &[String],
+        spendable: f64,
+    ) -> bool {
+        token.is_valid(deny_list, strategy, spendable)
+    }
+
+    fn rank(&self, a: &Token, b: &Token) -> Ordering {
+        b.change.partial_cmp(&a.change).unwrap_or(Ordering::Equal)
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::SystemTime;
+
+use crate::prelude::*;
+
+/// Polls the strategy file and returns the new strategy when it changes
+#[derive(Debug, Clone)]
+pub struct StrategyWatcher {
+    pub path: String,
+    modified: Option<SystemTime>,
+}
+
+impl StrategyWatcher {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            modified: StrategyWatcher::modified(path),
+        }
+    }
+
+    fn modified(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Validated and hashed strategy if the file was modified since the last check.
+    /// An invalid file is reported once and ignored until it changes again.
+    pub fn reload(&mut self) -> Result<Option<Strategy>> {
+        let modified = StrategyWatcher::modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return Ok(None);
+        }
+        self.modified = modified;
+
+        let mut strategy = Strategy::load(&self.path)?;
+        strategy.validate()?;
+        strategy.hash = strategy.get_hash();
+        Ok(Some(strategy))
+    }
+}
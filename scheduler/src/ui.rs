@@ -25,8 +25,15 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
                 &change_header,
                 &range_header,
                 &vol_header,
+                "Trend",
+                "RSI",
+                "ATR",
+                "Vol Z",
+                "Corr",
+                "Flow",
                 "Change (24h)",
                 "Volume (24h)",
+                "Spread",
                 " Status ",
             ]);
         //print token rows
@@ -44,7 +51,7 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
                     .add_attribute(Attribute::Fraktur),
             );
             //Last candle
-            let blank = Candlestick::new(t.price);
+            let blank = Candlestick::new(t.price, app.time.utc);
             let last = t.candlesticks.last().unwrap_or(&blank);
             if last.change < 0.00 {
                 token_row.push(
@@ -104,6 +111,33 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
             //vol
             token_row.push(Cell::new(format!("{:.0}", t.vol)).set_alignment(CellAlignment::Center));
 
+            //indicators
+            let ind = &t.indicators;
+            token_row.push(match ind.uptrend() {
+                Some(true) => Cell::new("▲")
+                    .set_alignment(CellAlignment::Center)
+                    .fg(Color::Green),
+                Some(false) => Cell::new("▼")
+                    .set_alignment(CellAlignment::Center)
+                    .fg(Color::Red),
+                None => Cell::new("-").set_alignment(CellAlignment::Center),
+            });
+            //rsi, atr, vol z-score and the highest correlation with the portfolio
+            let correlation = t.max_correlation(&account.portfolio);
+            for value in [ind.rsi, ind.atr, ind.vol_zscore, correlation] {
+                token_row.push(
+                    Cell::new(value.map_or_else(|| "-".to_string(), |x| format!("{:.2}", x)))
+                        .set_alignment(CellAlignment::Center),
+                );
+            }
+            //buy/sell imbalance of the current minute trades
+            token_row.push(match t.imbalance {
+                Some(x) => Cell::new(format!("{:+.0}%", x))
+                    .set_alignment(CellAlignment::Center)
+                    .fg(if x >= 0.0 { Color::Green } else { Color::Red }),
+                None => Cell::new("-").set_alignment(CellAlignment::Center),
+            });
+
             //change 24h
             if t.change24h <= 0.00 {
                 token_row.push(
@@ -123,6 +157,10 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
             //vol 24h
             token_row
                 .push(Cell::new(format!("{:.0}", t.vol24h)).set_alignment(CellAlignment::Center));
+            //spread
+            token_row.push(
+                Cell::new(format!("{:.3}%", t.book.spread)).set_alignment(CellAlignment::Center),
+            );
 
             //Status
             if let Some(token) = account.portfolio.iter().find(|s| t.instid == s.instid) {
@@ -205,7 +243,7 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
                     .add_attribute(Attribute::Fraktur),
             );
             //Last candle
-            let blank = Candlestick::new(t.price);
+            let blank = Candlestick::new(t.price, app.time.utc);
             let last = t.candlesticks.last().unwrap_or(&blank);
             if last.change < 0.00 {
                 token_row.push(
@@ -440,6 +478,41 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
             table_instids.add_row(token_row);
         }
         tables.push(table_instids);
+
+        //Portfolio exposure
+        let exposure = Exposure::new(account);
+        let mut table_exposure = Table::new();
+        table_exposure
+            .load_preset(UTF8_FULL)
+            .apply_modifier(UTF8_ROUND_CORNERS)
+            .set_content_arrangement(ContentArrangement::Disabled)
+            .set_width(TABLE_WIDTH)
+            .set_header(vec![
+                "Exposure",
+                "Of Balance",
+                "Positions",
+                "Largest",
+                "Avg Corr",
+                "Max Corr",
+            ]);
+        let correlation = |value: Option<f32>| {
+            let cell = Cell::new(value.map_or_else(|| "-".to_string(), |x| format!("{:.2}", x)))
+                .set_alignment(CellAlignment::Center);
+            match (value, cfg.strategy.max_correlation) {
+                (Some(x), Some(max)) if x > max => cell.fg(Color::Red),
+                _ => cell,
+            }
+        };
+        table_exposure.add_row(vec![
+            Cell::new(format!("$ {:.2}", exposure.usdt)).set_alignment(CellAlignment::Center),
+            Cell::new(format!("{:.2} %", exposure.pct)).set_alignment(CellAlignment::Center),
+            Cell::new(exposure.positions).set_alignment(CellAlignment::Center),
+            Cell::new(format!("{:.2} %", exposure.largest_pct))
+                .set_alignment(CellAlignment::Center),
+            correlation(exposure.avg_correlation),
+            correlation(exposure.max_correlation),
+        ]);
+        tables.push(table_exposure);
     }
     if cfg.ui.balance {
         let mut table_account = Table::new();
@@ -598,6 +671,64 @@ pub fn display(cfg: &AppConfig, app: &App, account: &Account) -> Result<Vec<Tabl
 
         table_config.add_row(row);
         tables.push(table_config);
+
+        //Closed rounds by exit reason
+        if !app.risk.exit_reasons.is_empty() {
+            let mut table_exits = Table::new();
+            table_exits
+                .load_preset(UTF8_FULL)
+                .apply_modifier(UTF8_ROUND_CORNERS)
+                .set_content_arrangement(ContentArrangement::Disabled)
+                .set_width(TABLE_WIDTH)
+                .set_header(
+                    app.risk
+                        .exit_reasons
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<String>>(),
+                );
+            table_exits.add_row(
+                app.risk
+                    .exit_reasons
+                    .values()
+                    .map(|count| Cell::new(count).set_alignment(CellAlignment::Center))
+                    .collect::<Vec<Cell>>(),
+            );
+            tables.push(table_exits);
+        }
+
+        //Market regime and the change of each reference instrument/timeframe
+        if app.regime.is_enabled() {
+            let mut table_regime = Table::new();
+            let mut header = vec!["Regime".to_string()];
+            header.extend(app.regime.changes.iter().map(|(instid, timeframe, _)| {
+                format!("{} {}m", instid.replace("-USDT", ""), timeframe)
+            }));
+            table_regime
+                .load_preset(UTF8_FULL)
+                .apply_modifier(UTF8_ROUND_CORNERS)
+                .set_content_arrangement(ContentArrangement::Disabled)
+                .set_width(TABLE_WIDTH)
+                .set_header(header);
+            let mut row = vec![Cell::new(app.regime.regime.to_string())
+                .set_alignment(CellAlignment::Center)
+                .fg(match app.regime.regime {
+                    MarketRegime::RiskOn => Color::Green,
+                    MarketRegime::Neutral => Color::White,
+                    MarketRegime::RiskOff => Color::Red,
+                })];
+            row.extend(app.regime.changes.iter().map(|(_, _, change)| {
+                Cell::new(format!("{:.2} %", change))
+                    .set_alignment(CellAlignment::Center)
+                    .fg(if *change >= 0.0 {
+                        Color::Green
+                    } else {
+                        Color::Red
+                    })
+            }));
+            table_regime.add_row(row);
+            tables.push(table_regime);
+        }
     }
     if cfg.ui.system {
         let mut table_time = Table::new();
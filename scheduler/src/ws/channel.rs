@@ -10,6 +10,8 @@ pub struct Data {
     pub earnings: f64,
     pub fee_spend: f64,
     pub tokens: Vec<Token>,
+    //Market regime, when the detector is enabled
+    pub regime: Option<String>,
     pub ts: DateTime<Utc>,
 }
 
@@ -63,6 +65,7 @@ pub async fn transmit(server: WebSocket, mut receiver: mpsc::Receiver<Data>) ->
                         "earnings": &data.earnings,
                         "change": &data.change,
                         "fee_spend": &data.fee_spend,
+                        "regime": &data.regime,
                     }).to_string(),
                     "ts": ts
                     })